@@ -0,0 +1,179 @@
+use std::path::Path;
+
+use crate::{
+    vector::{Vector, Quaternion},
+    transform::Pose,
+    utils
+};
+
+
+/// Values that can be blended between keyframes.
+pub trait Interpolate: Copy {
+    /// Blend from `self` at `t = 0` to `other` at `t = 1`.
+    fn interpolate(&self, other: &Self, t: f64) -> Self;
+}
+
+impl Interpolate for f64 {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self + t * (other - self)
+    }
+}
+
+/// Also covers `Point` and `Color`.
+impl Interpolate for Vector {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        *self + t * (*other - *self)
+    }
+}
+
+impl Interpolate for Quaternion {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self.slerp(*other, t)
+    }
+}
+
+impl Interpolate for Pose {
+    fn interpolate(&self, other: &Self, t: f64) -> Self {
+        self.lerp(other, t)
+    }
+}
+
+
+/// How a track moves from one keyframe to the next.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Easing {
+    /// Hold each value until the next keyframe.
+    Step,
+    Linear,
+    /// Ease in and out of each keyframe.
+    Smooth
+}
+
+
+/// Track holds the keyframes of a property over time.
+pub struct Track<T: Interpolate> {
+    /// Sorted by time.
+    keys: Vec<(f64, T)>,
+    easing: Easing
+}
+
+impl<T: Interpolate> Track<T> {
+    pub fn new(easing: Easing) -> Self {
+        Self { keys: Vec::new(), easing }
+    }
+
+    /// Track holding `value` at all times.
+    pub fn constant(value: T) -> Self {
+        let mut track = Self::new(Easing::Step);
+        track.add_key(0.0, value);
+        track
+    }
+
+    /// Add a keyframe, replacing any other at the same time.
+    pub fn add_key(&mut self, time: f64, value: T) {
+        match self.keys.binary_search_by(|(t, _)| t.total_cmp(&time)) {
+            Ok(index) => self.keys[index].1 = value,
+            Err(index) => self.keys.insert(index, (time, value))
+        }
+    }
+
+    /// Value at `time`, holding the first and last keyframes outside of the track.
+    /// Panic if the track has no keyframes.
+    pub fn at(&self, time: f64) -> T {
+        let next = self.keys.partition_point(|(t, _)| *t <= time);
+        if next == 0 {
+            return self.keys[0].1;
+        }
+        if next == self.keys.len() {
+            return self.keys[next - 1].1;
+        }
+
+        let (t0, v0) = self.keys[next - 1];
+        let (t1, v1) = self.keys[next];
+        let t = (time - t0) / (t1 - t0);
+        let t = match self.easing {
+            Easing::Step => 0.0,
+            Easing::Linear => t,
+            Easing::Smooth => t * t * (3.0 - 2.0 * t)
+        };
+        v0.interpolate(&v1, utils::clamp(t, 0.0, 1.0))
+    }
+}
+
+
+/// Return the path of `frame` from `pattern`, replacing the last run of `#` with the
+/// zero-padded frame number, or if there is none, appending the number before the extension of the file name.
+pub fn frame_path(pattern: &str, frame: usize) -> String {
+    match pattern.rfind('#') {
+        Some(end) => {
+            let start = pattern[..end].trim_end_matches('#').len();
+            let width = end + 1 - start;
+            format!("{}{:0width$}{}", &pattern[..start], frame, &pattern[end + 1..], width = width)
+        },
+        // Only the file name has an extension, not the directories leading to it.
+        None => match Path::new(pattern).extension() {
+            Some(extension) => {
+                let dot = pattern.len() - extension.len() - 1;
+                format!("{}_{:04}{}", &pattern[..dot], frame, &pattern[dot..])
+            },
+            None => format!("{}_{:04}", pattern, frame)
+        }
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(easing: Easing) -> Track<f64> {
+        let mut track = Track::new(easing);
+        track.add_key(2.0, 10.0);
+        track.add_key(0.0, 0.0);
+        track
+    }
+
+    #[test]
+    fn track_holds_first_and_last_keys() {
+        for easing in [Easing::Step, Easing::Linear, Easing::Smooth] {
+            let track = track(easing);
+            assert_eq!((track.at(-1.0), track.at(0.0)), (0.0, 0.0));
+            assert_eq!((track.at(2.0), track.at(3.0)), (10.0, 10.0));
+        }
+    }
+
+    #[test]
+    fn track_eases_between_keys() {
+        assert_eq!(track(Easing::Step).at(1.9), 0.0);
+        assert_eq!(track(Easing::Linear).at(0.5), 2.5);
+        // Smoothstep is slower than linear near the keys and matches it halfway.
+        let smooth = track(Easing::Smooth);
+        assert!((smooth.at(0.5) - 1.5625).abs() < 1E-12);
+        assert!((smooth.at(1.0) - 5.0).abs() < 1E-12);
+        assert!((smooth.at(1.5) - 8.4375).abs() < 1E-12);
+    }
+
+    #[test]
+    fn track_replaces_keys_at_the_same_time() {
+        let mut track = track(Easing::Linear);
+        track.add_key(2.0, 20.0);
+        assert_eq!(track.at(1.0), 10.0);
+        assert_eq!(Track::constant(4.0).at(100.0), 4.0);
+    }
+
+    #[test]
+    fn frame_path_pads_the_last_run_of_hashes() {
+        assert_eq!(frame_path("frame_####.ppm", 7), "frame_0007.ppm");
+        assert_eq!(frame_path("take#2/frame_##.ppm", 123), "take#2/frame_123.ppm");
+        assert_eq!(frame_path("out/#", 5), "out/5");
+    }
+
+    #[test]
+    fn frame_path_appends_the_frame_without_hashes() {
+        assert_eq!(frame_path("render.ppm", 12), "render_0012.ppm");
+        assert_eq!(frame_path("render", 3), "render_0003");
+        assert_eq!(frame_path("out.d/render", 3), "out.d/render_0003");
+        assert_eq!(frame_path("out.d/render.ppm", 3), "out.d/render_0003.ppm");
+        assert_eq!(frame_path(".render", 3), ".render_0003");
+    }
+}
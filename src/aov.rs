@@ -0,0 +1,166 @@
+use std::io;
+
+use crate::{
+    color::Color,
+    vector::Vector,
+    point::Point,
+    image::{Image, Region}
+};
+
+
+/// Arbitrary output variables, images of what the camera sees besides the final color,
+/// for compositing and denoising.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Aov {
+    /// Color of the first surface hit, before lighting.
+    Albedo,
+    /// Shading normal of the first surface hit.
+    Normal,
+    /// Distance along the camera ray to the first surface hit.
+    Depth,
+    /// World position of the first surface hit.
+    Position,
+    /// One plus the index of the first object hit in the scene, zero for none.
+    ObjectId,
+    /// One plus the index of the material of the first surface hit, numbered in order of first hit, zero for none.
+    MaterialId,
+    /// Light scattered towards the camera by the first surface or medium met.
+    Direct,
+    /// Light reaching the camera after more than one interaction.
+    Indirect
+}
+
+impl Aov {
+    pub const ALL: [Aov; 8] = [
+        Aov::Albedo, Aov::Normal, Aov::Depth, Aov::Position,
+        Aov::ObjectId, Aov::MaterialId, Aov::Direct, Aov::Indirect
+    ];
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Aov::Albedo => "albedo",
+            Aov::Normal => "normal",
+            Aov::Depth => "depth",
+            Aov::Position => "position",
+            Aov::ObjectId => "object_id",
+            Aov::MaterialId => "material_id",
+            Aov::Direct => "direct",
+            Aov::Indirect => "indirect"
+        }
+    }
+
+    fn index(&self) -> usize {
+        Self::ALL.iter().position(|aov| aov == self).unwrap()
+    }
+}
+
+
+/// What a single camera ray sees. Surface values are zero when the ray hits nothing.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct AovSample {
+    pub albedo: Color,
+    pub normal: Vector,
+    pub depth: f64,
+    pub position: Point,
+    pub object_id: Option<usize>,
+    pub material_id: Option<usize>,
+    pub direct: Color,
+    pub indirect: Color
+}
+
+
+/// Aovs accumulates the AOV samples of each pixel. Values are averaged over the samples
+/// of the pixel, except for IDs which can't be blended and come from its first sample.
+pub struct Aovs {
+    width: usize,
+    height: usize,
+    /// Sum of the samples of each pixel, per AOV.
+    layers: Vec<Vec<Color>>,
+    counts: Vec<usize>
+}
+
+impl Aovs {
+    pub fn new(width: usize, height: usize) -> Self {
+        Self {
+            width,
+            height,
+            layers: vec![vec![Color::default(); width * height]; Aov::ALL.len()],
+            counts: vec![0; width * height]
+        }
+    }
+
+    pub fn add_sample(&mut self, x: usize, y: usize, sample: &AovSample) {
+        let index = y * self.width + x;
+        let first = self.counts[index] == 0;
+        self.counts[index] += 1;
+
+        let id = |id: Option<usize>| {
+            let id = id.map_or(0.0, |id| (id + 1) as f64);
+            Color::new(id, id, id)
+        };
+        let values = [
+            (Aov::Albedo, sample.albedo),
+            (Aov::Normal, sample.normal),
+            (Aov::Depth, Color::new(sample.depth, sample.depth, sample.depth)),
+            (Aov::Position, sample.position),
+            (Aov::Direct, sample.direct),
+            (Aov::Indirect, sample.indirect)
+        ];
+        for (aov, value) in values {
+            self.layers[aov.index()][index] += value;
+        }
+        if first {
+            self.layers[Aov::ObjectId.index()][index] = id(sample.object_id);
+            self.layers[Aov::MaterialId.index()][index] = id(sample.material_id);
+        }
+    }
+
+    /// Value of `aov` at pixel `(x, y)`.
+    pub fn get_pixel(&self, aov: Aov, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        let value = self.layers[aov.index()][index];
+        match aov {
+            Aov::ObjectId | Aov::MaterialId => value,
+            _ if self.counts[index] == 0 => value,
+            _ => value / self.counts[index] as f64
+        }
+    }
+
+    pub fn image(&self, aov: Aov) -> Image {
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                image.set_pixel(x, y, self.get_pixel(aov, x, y));
+            }
+        }
+        image
+    }
+
+    /// Copy of the AOVs of `region`, see `Film::crop`.
+    pub fn crop(&self, region: Region, keep_size: bool) -> Aovs {
+        let (x0, y0) = if keep_size { (0, 0) } else { (region.x, region.y) };
+        let mut aovs = if keep_size {
+            Aovs::new(self.width, self.height)
+        } else {
+            Aovs::new(region.width, region.height)
+        };
+        for y in region.rows() {
+            for x in region.columns() {
+                let (from, to) = (y * self.width + x, (y - y0) * aovs.width + x - x0);
+                for (layer, cropped) in self.layers.iter().zip(aovs.layers.iter_mut()) {
+                    cropped[to] = layer[from];
+                }
+                aovs.counts[to] = self.counts[from];
+            }
+        }
+        aovs
+    }
+
+    /// Save every AOV as a floating point image named `<prefix>_<name>.pfm`.
+    pub fn save(&self, prefix: &str) -> io::Result<()> {
+        for aov in Aov::ALL {
+            self.image(aov).save(format!("{}_{}.pfm", prefix, aov.name()))?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,255 @@
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    point::Point,
+    vector::Vector,
+    object::{Object, Sphere, Plane, Quad, AxisAlignedBox, Cylinder, Cone, Disk, Annulus, Torus},
+    material::Material,
+    light::Light,
+    volume::{Volume, Medium},
+    scene::Scene,
+    error::{Error, Result}
+};
+
+
+/// MaterialLibrary holds materials by name, so that objects can share them.
+#[derive(Default, Clone)]
+pub struct MaterialLibrary {
+    materials: HashMap<String, Rc<dyn Material>>
+}
+
+impl MaterialLibrary {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add `material` under `name`, replacing any material of that name.
+    pub fn add<M: Material + 'static>(&mut self, name: &str, material: M) -> Rc<dyn Material> {
+        let material: Rc<dyn Material> = Rc::new(material);
+        self.materials.insert(name.to_string(), material.clone());
+        material
+    }
+
+    pub fn get(&self, name: &str) -> Result<Rc<dyn Material>> {
+        self.materials.get(name).cloned().ok_or_else(|| Error::UnknownMaterial(name.to_string()))
+    }
+
+    pub fn names(&self) -> impl Iterator<Item = &str> {
+        self.materials.keys().map(String::as_str)
+    }
+}
+
+
+/// SceneBuilder assembles a `Scene` step by step. Left alone, the image is 1920x1080
+/// seen by `Camera::new` from the origin, without lights or fog. Mistakes, like naming
+/// a missing material, are reported by `build`.
+///
+/// ```no_run
+/// use ray_tracer::{Scene, Point, Vector, Color, material::Metal};
+///
+/// let scene = Scene::builder()
+///     .size(640, 360)
+///     .material("gold", Metal::default().with_color(Color::from_hex(0xffd384)).with_reflection(0.8))
+///     .sphere(Point::new(0.0, 0.0, -2.0), 0.5, "gold")
+///     .plane(Vector::new(0.0, -1.0, 0.0), 0.5, "gold")
+///     .light(Point::new(0.0, 3.0, 2.0), Color::new(1.0, 1.0, 1.0))
+///     .build()?;
+/// # Ok::<(), ray_tracer::Error>(())
+/// ```
+pub struct SceneBuilder {
+    camera: Camera,
+    width: usize,
+    height: usize,
+    materials: MaterialLibrary,
+    objects: Vec<Rc<dyn Object>>,
+    lights: Vec<Light>,
+    volumes: Vec<Rc<dyn Volume>>,
+    fog: Option<Medium>,
+    /// First mistake made while building.
+    error: Option<Error>
+}
+
+impl Default for SceneBuilder {
+    fn default() -> Self {
+        Self {
+            camera: Camera::new(Point::new(0.0, 0.0, 0.0)),
+            width: 1920,
+            height: 1080,
+            materials: MaterialLibrary::new(),
+            objects: Vec::new(),
+            lights: Vec::new(),
+            volumes: Vec::new(),
+            fog: None,
+            error: None
+        }
+    }
+}
+
+impl SceneBuilder {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn camera(self, camera: Camera) -> Self {
+        Self { camera, ..self }
+    }
+
+    /// Size of the image in pixels.
+    pub fn size(self, width: usize, height: usize) -> Self {
+        Self { width, height, ..self }
+    }
+
+    /// Add `material` to the library under `name`.
+    pub fn material<M: Material + 'static>(mut self, name: &str, material: M) -> Self {
+        self.materials.add(name, material);
+        self
+    }
+
+    /// Use the materials of `library`, on top of those already added.
+    pub fn materials(mut self, library: &MaterialLibrary) -> Self {
+        for (name, material) in &library.materials {
+            self.materials.materials.insert(name.clone(), material.clone());
+        }
+        self
+    }
+
+    /// Material named `name`, for objects built outside of the builder.
+    pub fn material_named(&self, name: &str) -> Result<Rc<dyn Material>> {
+        self.materials.get(name)
+    }
+
+    /// Add a sphere made of the material named `material`.
+    pub fn sphere(self, center: Point, radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Sphere::new(center, radius, material))
+    }
+
+    /// Add a plane made of the material named `material`, see `Plane::new`.
+    pub fn plane(self, unit_normal: Vector, distance: f64, material: &str) -> Self {
+        self.object_of(material, |material| Plane::new(unit_normal, distance, material))
+    }
+
+    /// Add a quad made of the material named `material`, see `Quad::new`.
+    pub fn quad(self, origin: Point, x: Vector, y: Vector, material: &str) -> Self {
+        self.object_of(material, |material| Quad::new(origin, x, y, material))
+    }
+
+    /// Add a box of opposite corners `a` and `b` made of the material named `material`.
+    pub fn axis_aligned_box(self, a: Point, b: Point, material: &str) -> Self {
+        self.object_of(material, |material| AxisAlignedBox::new(a, b, material))
+    }
+
+    /// Add a cylinder made of the material named `material`, see `Cylinder::new`.
+    pub fn cylinder(self, base: Point, axis: Vector, radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Cylinder::new(base, axis, radius, material))
+    }
+
+    /// Add a cone made of the material named `material`, see `Cone::new`.
+    pub fn cone(self, base: Point, axis: Vector, radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Cone::new(base, axis, radius, material))
+    }
+
+    /// Add a disk made of the material named `material`, see `Disk::new`.
+    pub fn disk(self, center: Point, normal: Vector, radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Disk::new(center, normal, radius, material))
+    }
+
+    /// Add an annulus made of the material named `material`, see `Annulus::new`.
+    pub fn annulus(self, center: Point, normal: Vector, inner_radius: f64, outer_radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Annulus::new(center, normal, inner_radius, outer_radius, material))
+    }
+
+    /// Add a torus made of the material named `material`, see `Torus::new`.
+    pub fn torus(self, center: Point, axis: Vector, major_radius: f64, minor_radius: f64, material: &str) -> Self {
+        self.object_of(material, |material| Torus::new(center, axis, major_radius, minor_radius, material))
+    }
+
+    /// Add `object` as it is. Objects without a helper above, like a `Csg` or an `Instance`,
+    /// get their materials from `material_named`.
+    pub fn object<O: Object + 'static>(mut self, object: O) -> Self {
+        self.objects.push(Rc::new(object));
+        self
+    }
+
+    pub fn light(mut self, position: Point, color: Color) -> Self {
+        self.lights.push(Light::new(position, color));
+        self
+    }
+
+    pub fn volume<V: Volume + 'static>(mut self, volume: V) -> Self {
+        self.volumes.push(Rc::new(volume));
+        self
+    }
+
+    pub fn fog(self, medium: Medium) -> Self {
+        Self { fog: Some(medium), ..self }
+    }
+
+    /// Return the scene, or the first mistake made while building it, or the problems
+    /// found by `Scene::validate`.
+    pub fn build(self) -> Result<Scene> {
+        if let Some(error) = self.error {
+            return Err(error);
+        }
+        let mut scene = Scene::new(self.camera, self.objects, self.lights, self.width, self.height);
+        scene.volumes = self.volumes;
+        scene.fog = self.fog;
+        scene.validate()?;
+        Ok(scene)
+    }
+
+    /// Add the object made by `make` of the material named `material`.
+    fn object_of<O: Object + 'static>(self, material: &str, make: impl FnOnce(Rc<dyn Material>) -> O) -> Self {
+        match self.material_named(material) {
+            Ok(material) => self.object(make(material)),
+            Err(error) => self.fail(error)
+        }
+    }
+
+    fn fail(mut self, error: Error) -> Self {
+        self.error.get_or_insert(error);
+        self
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::material::Metal;
+
+    #[test]
+    fn objects_share_named_materials() {
+        let scene = SceneBuilder::new()
+            .material("metal", Metal::default())
+            .sphere(Point::new(0.0, 0.0, -2.0), 0.5, "metal")
+            .quad(Point::new(0.0, 0.0, -3.0), Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), "metal")
+            .axis_aligned_box(Point::new(1.0, 0.0, -3.0), Point::new(2.0, 1.0, -2.0), "metal")
+            .cylinder(Point::new(-1.0, 0.0, -3.0), Vector::new(0.0, 1.0, 0.0), 0.2, "metal")
+            .cone(Point::new(-2.0, 0.0, -3.0), Vector::new(0.0, 1.0, 0.0), 0.2, "metal")
+            .disk(Point::new(0.0, -1.0, -3.0), Vector::new(0.0, 1.0, 0.0), 0.5, "metal")
+            .annulus(Point::new(0.0, -1.0, -4.0), Vector::new(0.0, 1.0, 0.0), 0.2, 0.5, "metal")
+            .torus(Point::new(0.0, 1.0, -4.0), Vector::new(0.0, 1.0, 0.0), 0.5, 0.1, "metal")
+            .build()
+            .expect("all the materials exist");
+        assert_eq!(scene.objects.len(), 8);
+        let material = scene.objects[0].get_material();
+        assert!(scene.objects.iter().all(|object| Rc::ptr_eq(&object.get_material(), &material)), "objects don't share the material");
+    }
+
+    #[test]
+    fn unknown_material_fails_to_build() {
+        let result = SceneBuilder::new()
+            .material("metal", Metal::default())
+            .torus(Point::new(0.0, 0.0, -2.0), Vector::new(0.0, 1.0, 0.0), 0.5, 0.1, "gold")
+            .sphere(Point::new(0.0, 0.0, -2.0), 0.5, "silver")
+            .build();
+        match result {
+            Err(Error::UnknownMaterial(name)) => assert_eq!(name, "gold", "the first mistake is reported"),
+            Err(error) => panic!("unexpected error: {}", error),
+            Ok(_) => panic!("a scene was built with an unknown material")
+        }
+    }
+}
@@ -1,3 +1,89 @@
+use crate::{
+    point::Point,
+    vector::{Vector, Matrix3},
+    ray::Ray,
+    utils
+};
+
+
+/// Camera is a pinhole camera generating the primary rays of the image.
 pub struct Camera {
-    
-}
\ No newline at end of file
+    pub position: Point,
+    /// Columns are the right, up and backward directions of the camera in world space.
+    orientation: Matrix3,
+    /// Half the width of the image plane at unit distance in front of the camera.
+    half_width: f64,
+
+    /// Times at which the shutter opens and closes, rays are spread between them.
+    pub shutter_open: f64,
+    pub shutter_close: f64
+}
+
+impl Camera {
+    /// Camera at `position` looking down -z with a horizontal field of view of 90 degrees.
+    pub fn new(position: Point) -> Self {
+        Self {
+            position,
+            orientation: Matrix3::identity(),
+            half_width: 1.0,
+            shutter_open: 0.0,
+            shutter_close: 0.0
+        }
+    }
+
+    /// Camera at `position` looking at `target`, with `horizontal_fov` in degrees.
+    pub fn look_at(position: Point, target: Point, up: Vector, horizontal_fov: f64) -> Self {
+        let backward = (position - target).normalize();
+        let right = up.cross(backward).normalize();
+        Self {
+            position,
+            orientation: Matrix3::from_columns(right, backward.cross(right), backward),
+            half_width: (utils::degrees_to_radians(horizontal_fov) / 2.0).tan(),
+            ..Self::new(position)
+        }
+    }
+
+    /// Horizontal field of view in degrees.
+    pub fn horizontal_fov(&self) -> f64 {
+        2.0 * self.half_width.atan().to_degrees()
+    }
+
+    pub fn set_shutter(&mut self, open: f64, close: f64) {
+        self.shutter_open = open;
+        self.shutter_close = close;
+    }
+
+    /// Return the ray through the point `(x, y)` of the image plane, where `x` spans `[-1, 1]`
+    /// from left to right and `y` grows upwards at the same scale. `time` in `[0, 1)` picks
+    /// the moment between the opening and closing of the shutter.
+    pub fn get_ray(&self, x: f64, y: f64, time: f64) -> Ray {
+        let direction = self.orientation * Vector::new(x * self.half_width, y * self.half_width, -1.0);
+        Ray::with_time(
+            self.position,
+            direction,
+            self.shutter_open + time * (self.shutter_close - self.shutter_open)
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::SamplerKind;
+
+    #[test]
+    fn rays_are_spread_over_the_shutter() {
+        let mut camera = Camera::new(Point::new(0.0, 0.0, 0.0));
+        camera.set_shutter(1.0, 3.0);
+        for kind in [SamplerKind::Random, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let mut sampler = kind.create(16, 7);
+            let times: Vec<f64> = (0..16).map(|s| {
+                sampler.start_sample(2, 3, s);
+                camera.get_ray(0.0, 0.0, sampler.next_1d()).time
+            }).collect();
+            assert!(times.iter().all(|time| (1.0..3.0).contains(time)), "{:?}: {:?}", kind, times);
+            assert!(times.iter().any(|time| *time < 2.0) && times.iter().any(|time| *time >= 2.0), "{:?}: {:?}", kind, times);
+        }
+    }
+}
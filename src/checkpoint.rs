@@ -0,0 +1,140 @@
+use std::fs::{self, File};
+use std::io::{self, BufReader, BufWriter, Read, Write};
+use std::ops::Range;
+use std::path::Path;
+
+use crate::{
+    film::{self, Film},
+    filter::Filter,
+    image::Region,
+    sampler::SamplerKind
+};
+
+
+const MAGIC: &[u8; 8] = b"RTCKPT03";
+
+
+/// Checkpoint records how far a progressive render got, so that it can be resumed from its film.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Checkpoint {
+    /// Settings of the render, which must match to resume it.
+    pub seed: u64,
+    pub max_depth: isize,
+    pub rays_per_pixel: usize,
+    pub max_rays_per_pixel: usize,
+    /// Error threshold of adaptive sampling, if any.
+    pub adaptive_threshold: Option<f64>,
+    pub sampler: SamplerKind,
+    pub filter: Filter,
+    /// Pixels sampled, the whole image or the crop region with its margin.
+    pub region: Region,
+    /// Indices of the samples of the pass in progress.
+    pub samples: Range<usize>,
+    /// First row of the pass left to render.
+    pub row: usize
+}
+
+impl Checkpoint {
+    /// Whether `other` was saved by a render of the same settings, however far it got.
+    pub fn same_settings(&self, other: &Checkpoint) -> bool {
+        *other == Checkpoint { samples: other.samples.clone(), row: other.row, ..self.clone() }
+    }
+
+    /// Save the checkpoint and `film` to `path`. The file is replaced only once fully written,
+    /// so that an interruption never loses the previous checkpoint.
+    pub fn save<P: AsRef<Path>>(&self, path: P, film: &Film) -> io::Result<()> {
+        let path = path.as_ref();
+        let mut temporary = path.as_os_str().to_owned();
+        temporary.push(".tmp");
+
+        let mut writer = BufWriter::new(File::create(&temporary)?);
+        writer.write_all(MAGIC)?;
+        let (filter, parameters) = encode_filter(self.filter);
+        let values = [
+            self.seed, self.max_depth as u64, self.rays_per_pixel as u64, self.max_rays_per_pixel as u64,
+            self.adaptive_threshold.is_some() as u64, self.adaptive_threshold.unwrap_or(0.0).to_bits(),
+            encode_sampler(self.sampler), filter, parameters[0].to_bits(), parameters[1].to_bits(), parameters[2].to_bits(),
+            self.region.x as u64, self.region.y as u64, self.region.width as u64, self.region.height as u64,
+            self.samples.start as u64, self.samples.end as u64, self.row as u64
+        ];
+        for value in values {
+            writer.write_all(&value.to_le_bytes())?;
+        }
+        film.write_to(&mut writer)?;
+        writer.into_inner().map_err(|error| error.into_error())?.sync_all()?;
+
+        fs::rename(&temporary, path)
+    }
+
+    /// Load a checkpoint and its film, which splats samples with the filter of the checkpoint.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<(Self, Film)> {
+        let mut reader = BufReader::new(File::open(path)?);
+        let mut magic = [0u8; 8];
+        reader.read_exact(&mut magic)?;
+        if &magic != MAGIC {
+            return Err(film::invalid_data("not a render checkpoint"));
+        }
+
+        let mut values = [0u64; 18];
+        for value in values.iter_mut() {
+            *value = film::read_u64(&mut reader)?;
+        }
+        let parameters = [f64::from_bits(values[8]), f64::from_bits(values[9]), f64::from_bits(values[10])];
+        let checkpoint = Self {
+            seed: values[0],
+            max_depth: values[1] as isize,
+            rays_per_pixel: values[2] as usize,
+            max_rays_per_pixel: values[3] as usize,
+            adaptive_threshold: if values[4] != 0 { Some(f64::from_bits(values[5])) } else { None },
+            sampler: decode_sampler(values[6])?,
+            filter: decode_filter(values[7], parameters)?,
+            region: Region::new(values[11] as usize, values[12] as usize, values[13] as usize, values[14] as usize),
+            samples: values[15] as usize..values[16] as usize,
+            row: values[17] as usize
+        };
+        let film = Film::read_from(&mut reader, checkpoint.filter)?;
+        Ok((checkpoint, film))
+    }
+}
+
+
+fn encode_sampler(sampler: SamplerKind) -> u64 {
+    match sampler {
+        SamplerKind::Random => 0,
+        SamplerKind::Stratified => 1,
+        SamplerKind::Halton => 2,
+        SamplerKind::Sobol => 3
+    }
+}
+
+fn decode_sampler(value: u64) -> io::Result<SamplerKind> {
+    match value {
+        0 => Ok(SamplerKind::Random),
+        1 => Ok(SamplerKind::Stratified),
+        2 => Ok(SamplerKind::Halton),
+        3 => Ok(SamplerKind::Sobol),
+        _ => Err(film::invalid_data("unknown sampler"))
+    }
+}
+
+/// Kind of `filter` and its parameters, the radius first.
+fn encode_filter(filter: Filter) -> (u64, [f64; 3]) {
+    match filter {
+        Filter::Box { radius } => (0, [radius, 0.0, 0.0]),
+        Filter::Tent { radius } => (1, [radius, 0.0, 0.0]),
+        Filter::Gaussian { radius, alpha } => (2, [radius, alpha, 0.0]),
+        Filter::Mitchell { radius, b, c } => (3, [radius, b, c]),
+        Filter::Lanczos { radius } => (4, [radius, 0.0, 0.0])
+    }
+}
+
+fn decode_filter(kind: u64, [radius, p1, p2]: [f64; 3]) -> io::Result<Filter> {
+    match kind {
+        0 => Ok(Filter::Box { radius }),
+        1 => Ok(Filter::Tent { radius }),
+        2 => Ok(Filter::Gaussian { radius, alpha: p1 }),
+        3 => Ok(Filter::Mitchell { radius, b: p1, c: p2 }),
+        4 => Ok(Filter::Lanczos { radius }),
+        _ => Err(film::invalid_data("unknown filter"))
+    }
+}
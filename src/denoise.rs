@@ -0,0 +1,145 @@
+use crate::{
+    color::Color,
+    image::Image
+};
+
+
+/// Coefficients of the B3 spline kernel, applied on 5 taps per axis.
+const KERNEL: [f64; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+
+/// Buffers of the first hits of the camera rays guiding the denoiser, see `aov::Aov`.
+pub struct Guides<'a> {
+    pub albedo: &'a Image,
+    pub normal: &'a Image,
+    pub depth: &'a Image
+}
+
+
+/// Edge-avoiding à-trous wavelet denoiser, after Dammertz et al. Each iteration blurs the image
+/// with a sparse 5x5 kernel twice as wide as the previous one, weighing every neighbor down by
+/// how much its color, albedo, normal and depth differ from those of the pixel.
+#[derive(Debug, Copy, Clone)]
+pub struct Denoiser {
+    pub iterations: usize,
+    /// Tolerated differences before a neighbor stops contributing, larger values blur more.
+    pub sigma_color: f64,
+    pub sigma_albedo: f64,
+    pub sigma_normal: f64,
+    /// Relative to the depth of the pixel.
+    pub sigma_depth: f64
+}
+
+impl Default for Denoiser {
+    fn default() -> Self {
+        Self {
+            iterations: 5,
+            sigma_color: 0.2,
+            sigma_albedo: 0.1,
+            sigma_normal: 0.3,
+            sigma_depth: 0.05
+        }
+    }
+}
+
+impl Denoiser {
+    /// Return a denoised copy of `image`. Panic if the guides don't have the size of the image.
+    pub fn denoise(&self, image: &Image, guides: &Guides) -> Image {
+        let (width, height) = (image.width(), image.height());
+        for guide in [guides.albedo, guides.normal, guides.depth] {
+            assert!(guide.width() == width && guide.height() == height, "guide size doesn't match image size");
+        }
+
+        let mut current = image.clone();
+        let mut sigma_color = self.sigma_color;
+        for iteration in 0..self.iterations {
+            let step = 1 << iteration;
+            let mut next = Image::new(width, height);
+            for y in 0..height {
+                for x in 0..width {
+                    next.set_pixel(x, y, self.filter_pixel(&current, guides, x, y, step, sigma_color));
+                }
+            }
+            current = next;
+            // Noise drops with each iteration, so should the tolerated color difference.
+            sigma_color /= 2.0;
+        }
+        current
+    }
+
+    fn filter_pixel(&self, image: &Image, guides: &Guides, x: usize, y: usize, step: usize, sigma_color: f64) -> Color {
+        let color = image.get_pixel(x, y);
+        let albedo = guides.albedo.get_pixel(x, y);
+        let normal = guides.normal.get_pixel(x, y);
+        let depth = guides.depth.get_pixel(x, y).0;
+
+        let mut sum = Color::default();
+        let mut total_weight = 0.0;
+        for (j, ky) in KERNEL.iter().enumerate() {
+            let qy = y as isize + (j as isize - 2) * step as isize;
+            if qy < 0 || qy >= image.height() as isize {
+                continue;
+            }
+            for (i, kx) in KERNEL.iter().enumerate() {
+                let qx = x as isize + (i as isize - 2) * step as isize;
+                if qx < 0 || qx >= image.width() as isize {
+                    continue;
+                }
+                let (qx, qy) = (qx as usize, qy as usize);
+
+                let q_color = image.get_pixel(qx, qy);
+                let depth_difference = (guides.depth.get_pixel(qx, qy).0 - depth) / depth.abs().max(1E-3);
+                let distance = squared_distance(color, q_color) / (sigma_color * sigma_color)
+                    + squared_distance(albedo, guides.albedo.get_pixel(qx, qy)) / (self.sigma_albedo * self.sigma_albedo)
+                    + squared_distance(normal, guides.normal.get_pixel(qx, qy)) / (self.sigma_normal * self.sigma_normal)
+                    + depth_difference * depth_difference / (self.sigma_depth * self.sigma_depth);
+
+                let weight = kx * ky * (-distance).exp();
+                sum += weight * q_color;
+                total_weight += weight;
+            }
+        }
+        // The pixel itself always has a positive weight.
+        sum / total_weight
+    }
+}
+
+fn squared_distance(a: Color, b: Color) -> f64 {
+    let d = a - b;
+    d.dot(d)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::sampler::Rng;
+
+    #[test]
+    fn denoise_smooths_noise_but_keeps_edges() {
+        let (width, height) = (32, 16);
+        let mut rng = Rng::new(1);
+        let mut image = Image::new(width, height);
+        let mut albedo = Image::new(width, height);
+        let normal = Image::new(width, height);
+        let depth = Image::new(width, height);
+        for y in 0..height {
+            for x in 0..width {
+                // Two flat halves of different albedo, with noise.
+                let base = if x < width / 2 { 0.2 } else { 0.8 };
+                let noisy = base + 0.2 * (rng.next_f64() - 0.5);
+                image.set_pixel(x, y, Color::new(noisy, noisy, noisy));
+                albedo.set_pixel(x, y, Color::new(base, base, base));
+            }
+        }
+
+        let guides = Guides { albedo: &albedo, normal: &normal, depth: &depth };
+        let denoised = Denoiser::default().denoise(&image, &guides);
+        for y in 0..height {
+            for x in 0..width {
+                let base = if x < width / 2 { 0.2 } else { 0.8 };
+                assert!((denoised.get_pixel(x, y).0 - base).abs() < 0.05);
+            }
+        }
+    }
+}
@@ -1,54 +1,416 @@
+use std::cell::RefCell;
+use std::io;
+use std::ops::Range;
+use std::time::{Duration, Instant};
+use std::rc::Rc;
+
 use crate::{
-    image::Image,
+    animation,
+    image::{Image, Region},
     ray::Ray,
     color::Color,
-    point::Point,
     scene::Scene,
-    object::{Object, Sphere},
-    vector::Vector,
-    HitRecord,
-    Rc,
+    volume::{VolumeSample, HenyeyGreenstein},
+    sampler::{Sampler, SamplerKind},
+    film::Film,
+    checkpoint::Checkpoint,
+    aov::{Aovs, AovSample},
+    material::Material,
+    filter::Filter,
+    stats::{self, RenderStats},
+    progress::{Progress, ProgressObserver, CancellationToken, Tracker},
+    hitrecord::HitRecord,
+    error::{Error, Result},
     utils
 };
 
 
+/// Settings of progressive rendering.
+pub struct Progressive {
+    /// Where to save the image.
+    pub path: String,
+    /// Time between saves of the image and checkpoint.
+    pub interval: Duration,
+    /// Stop rendering after this time.
+    pub budget: Option<Duration>,
+    /// Where to save the state of the render to resume it later.
+    pub checkpoint: Option<String>,
+    /// Continue the render saved in the checkpoint.
+    pub resume: bool
+}
+
+
 #[derive(Default)]
 pub struct RenderEngine {
     max_depth: isize,
-    min_displacement: f64,
-    rays_per_pixel: usize
+    rays_per_pixel: usize,
+    sampler: SamplerKind,
+    filter: Filter,
+    /// Error threshold and maximum number of rays per pixel of adaptive sampling.
+    adaptive: Option<(f64, usize)>,
+    /// All the randomness of a render derives from this seed.
+    seed: u64,
+    /// Region of the image to render, and whether to keep the full size of the image.
+    crop: Option<(Region, bool)>,
+    /// Statistics of the last render.
+    stats: RefCell<RenderStats>,
+    observer: RefCell<Option<Box<dyn ProgressObserver>>>,
+    cancellation: CancellationToken
 }
 
 impl RenderEngine {
     pub fn new(max_depth: isize, rays_per_pixel: usize) -> Self {
         Self {
-            max_depth, min_displacement: 0.001, rays_per_pixel
+            max_depth, rays_per_pixel, sampler: SamplerKind::default(), filter: Filter::default(), adaptive: None, seed: 0,
+            crop: None,
+            stats: RefCell::default(),
+            observer: RefCell::default(),
+            cancellation: CancellationToken::new()
         }
     }
+
+    /// Choose how the random numbers of the pixel samples are generated.
+    pub fn set_sampler(&mut self, sampler: SamplerKind) {
+        self.sampler = sampler;
+    }
+
+    /// Choose how samples are weighed into the pixels around them.
+    pub fn set_filter(&mut self, filter: Filter) {
+        self.filter = filter;
+    }
+
+    pub fn filter(&self) -> Filter {
+        self.filter
+    }
+
+    /// Keep sampling each pixel past `rays_per_pixel` until the standard error of its displayed
+    /// luminance falls below `threshold`, or `max_rays_per_pixel` rays were traced. Renders fail
+    /// if the maximum is below `rays_per_pixel`.
+    pub fn set_adaptive(&mut self, threshold: f64, max_rays_per_pixel: usize) {
+        self.adaptive = Some((threshold, max_rays_per_pixel));
+    }
+
+    /// Renders with the same scene, settings and seed give identical images.
+    pub fn set_seed(&mut self, seed: u64) {
+        self.seed = seed;
+    }
+
+    /// Report the progress of renders to `observer`, which may also cancel them.
+    pub fn set_observer<O: ProgressObserver + 'static>(&mut self, observer: O) {
+        self.observer = RefCell::new(Some(Box::new(observer)));
+    }
+
+    /// Render only the pixels of `region`, into a film or image the size of the region, or of the
+    /// whole image, black outside of the region, if `keep_size` is set. The region must fit in the image.
+    pub fn set_crop(&mut self, region: Region, keep_size: bool) {
+        self.crop = Some((region, keep_size));
+    }
+
+    /// Stop renders between rows once `token` is cancelled.
+    pub fn set_cancellation(&mut self, token: CancellationToken) {
+        self.cancellation = token;
+    }
+
+    /// Statistics of the last render, or of the last frame of an animation.
+    pub fn stats(&self) -> RenderStats {
+        self.stats.borrow().clone()
+    }
 }
 
 impl RenderEngine {
-    /// Find the nearest object hit by the ray in the scene.
-    fn find_nearest(&self, ray: Ray, scene: &Scene, t_min: f64, t_max: f64) -> Option<HitRecord> {
-        let mut min_record: Option<HitRecord> = None;
+    /// Find the nearest object hit by the ray in the scene, with its index among the objects of the scene.
+    fn find_nearest(&self, ray: Ray, scene: &Scene, t_min: f64, t_max: f64) -> Option<(usize, HitRecord)> {
+        let mut min_record: Option<(usize, HitRecord)> = None;
         let mut min_distance: f64 = utils::INFINITY;
 
-        for object in scene.objects.to_vec() {
-            let mut hit_record = HitRecord::new(Rc::clone(&object));
+        for (index, object) in scene.objects.iter().enumerate() {
+            let mut hit_record = HitRecord::new(Rc::clone(object));
             let is_hit = object.hit(ray, t_min, t_max, &mut hit_record);
-            if is_hit && (matches!(min_record, None) || hit_record.distance < min_distance) {
+            if is_hit && (min_record.is_none() || hit_record.distance < min_distance) {
                 min_distance = hit_record.distance;
-                min_record = Some(hit_record);
-                if min_distance <= 0.0 { eprintln!("ERROR: Negative distance to object."); }
+                min_record = Some((index, hit_record));
             }
         }
         min_record
     }
 
-    pub fn render(&self, scene: &mut Scene) -> Image {
+    /// Render the scene into a gamma-corrected image. Fail if the scene doesn't pass `Scene::validate`,
+    /// like every render method, or if the observer or the cancellation token cancels the render.
+    pub fn render(&self, scene: &Scene) -> Result<Image> {
+        let film = self.render_film(scene)?;
+        let start_time = Instant::now();
+        let image = Self::develop(&film);
+        self.stats.borrow_mut().add_phase("develop", start_time.elapsed());
+        Ok(image)
+    }
+
+    /// Render the scene into a film holding the filtered samples and the number of samples of each pixel.
+    pub fn render_film(&self, scene: &Scene) -> Result<Film> {
+        self.start(scene)?;
+        let mut film = Film::new(scene.width, scene.height, self.filter);
+        self.render_all(scene, &mut film, None)?;
+        Ok(self.crop_film(film))
+    }
+
+    /// Render the scene like `render_film`, also gathering the AOVs of the first hits of the camera rays.
+    pub fn render_with_aovs(&self, scene: &Scene) -> Result<(Film, Aovs)> {
+        self.start(scene)?;
+        let mut film = Film::new(scene.width, scene.height, self.filter);
+        let mut aovs = Aovs::new(scene.width, scene.height);
+        self.render_all(scene, &mut film, Some(&mut aovs))?;
+        match self.crop {
+            Some((region, keep_size)) => Ok((film.crop(region, keep_size), aovs.crop(region, keep_size))),
+            None => Ok((film, aovs))
+        }
+    }
+
+    /// Render successive passes into one film, each pass doubling the number of samples per pixel
+    /// until `rays_per_pixel`, or the maximum of adaptive sampling, is reached. The image, and the
+    /// checkpoint if any, are saved every `interval` of `settings` and once the render is over.
+    /// The render stops early, leaving a valid image behind, after the budget or when the observer
+    /// or the cancellation token cancels it.
+    pub fn render_progressive(&self, scene: &Scene, settings: &Progressive) -> Result<Film> {
+        self.start(scene)?;
+        let window = self.window(scene);
+        let rows = window.rows();
+        let start_time = Instant::now();
+        let mut last_save = start_time;
+        let mut error = None;
+
+        let mut checkpoint = self.checkpoint(window);
+        let mut film = Film::new(scene.width, scene.height, self.filter);
+        let mut resumed = false;
+        if let (Some(path), true) = (&settings.checkpoint, settings.resume) {
+            let (saved, saved_film) = Checkpoint::load(path)?;
+            if !saved.same_settings(&checkpoint) || !(rows.start..=rows.end).contains(&saved.row)
+                || saved_film.width() != scene.width || saved_film.height() != scene.height {
+                return Err(Error::CheckpointMismatch);
+            }
+            checkpoint = saved;
+            film = saved_film;
+            resumed = true;
+        }
+
+        let max_rays_per_pixel = checkpoint.max_rays_per_pixel;
+        // Fraction of the samples of the render taken once `rows_done` rows of the pass of `samples` are.
+        let fraction = |samples: &Range<usize>, rows_done: usize| {
+            let pass = samples.len() as f64 * rows_done as f64 / rows.len() as f64;
+            (samples.start as f64 + pass) / max_rays_per_pixel.max(1) as f64
+        };
+        let rows_done = checkpoint.row - rows.start;
+        let mut tracker = Tracker::new(fraction(&checkpoint.samples, rows_done));
+        // Tell the observer where a resumed render starts, which it may cancel right away.
+        let go_on = !resumed || self.report(&tracker.update(rows_done, rows.len(), checkpoint.samples.end, fraction(&checkpoint.samples, rows_done)));
+        while go_on && checkpoint.samples.start < checkpoint.samples.end {
+            let samples = checkpoint.samples.clone();
+            let pixels = Region { y: checkpoint.row, height: rows.end - checkpoint.row, ..window };
+            let completed = self.render_samples(scene, &mut film, None, samples.clone(), pixels, &mut |j, film| {
+                let rows_done = j + 1 - rows.start;
+                let progress = tracker.update(rows_done, rows.len(), samples.end, fraction(&samples, rows_done));
+                if last_save.elapsed() >= settings.interval {
+                    last_save = Instant::now();
+                    let saved = Checkpoint { row: j + 1, ..checkpoint.clone() };
+                    if let Err(e) = self.save_progress(film, settings, &saved) {
+                        error = Some(e);
+                        return false;
+                    }
+                }
+                checkpoint.row = j + 1;
+                self.report(&progress) && settings.budget.is_none_or(|budget| start_time.elapsed() < budget)
+            });
+            if let Some(error) = error {
+                return Err(Error::Io(error));
+            }
+            if checkpoint.row == rows.end {
+                checkpoint.samples = samples.end..(2 * samples.end).min(max_rays_per_pixel);
+                checkpoint.row = rows.start;
+            }
+            if !completed {
+                break;
+            }
+        }
+        self.report_finish(&tracker.last());
+
+        self.save_progress(&film, settings, &checkpoint)?;
+        Ok(self.crop_film(film))
+    }
+
+    /// Render for a wall-clock `budget` instead of a set number of rays per pixel, adding one sample
+    /// to every pixel per pass so that the image improves evenly. Adaptive sampling still skips
+    /// the pixels precise enough and caps the number of rays per pixel. The observer may end the render early.
+    /// `Film::samples_per_pixel` tells how many samples the budget allowed.
+    pub fn render_timed(&self, scene: &Scene, budget: Duration) -> Result<Film> {
+        self.start(scene)?;
+        let start_time = Instant::now();
+        let mut film = Film::new(scene.width, scene.height, self.filter);
+        let limit = self.adaptive.map(|(_, max_rays_per_pixel)| max_rays_per_pixel);
+
+        let window = self.window(scene);
+        let rows = window.rows();
+        let mut tracker = Tracker::new(0.0);
+        let mut s = 0;
+        while limit.is_none_or(|limit| s < limit) {
+            let completed = self.render_samples(scene, &mut film, None, s..s + 1, window, &mut |j, _| {
+                let rows_done = j + 1 - rows.start;
+                // Done when either the budget or the samples run out.
+                let mut fraction = start_time.elapsed().as_secs_f64() / budget.as_secs_f64().max(1E-9);
+                if let Some(limit) = limit {
+                    fraction = fraction.max((s as f64 + rows_done as f64 / rows.len() as f64) / limit.max(1) as f64);
+                }
+                let progress = tracker.update(rows_done, rows.len(), s + 1, fraction);
+                self.report(&progress) && start_time.elapsed() < budget
+            });
+            if !completed {
+                break;
+            }
+            s += 1;
+        }
+        self.report_finish(&tracker.last());
+        Ok(self.crop_film(film))
+    }
+
+    /// Trace every sample of every pixel in a single pass. Fail if the render is cancelled.
+    fn render_all(&self, scene: &Scene, film: &mut Film, aovs: Option<&mut Aovs>) -> Result<()> {
+        let samples_per_pixel = self.max_rays_per_pixel();
+        let window = self.window(scene);
+        let rows = window.rows();
+        let mut tracker = Tracker::new(0.0);
+        let completed = self.render_samples(scene, film, aovs, 0..samples_per_pixel, window, &mut |j, _| {
+            let rows_done = j + 1 - rows.start;
+            let progress = tracker.update(rows_done, rows.len(), samples_per_pixel, rows_done as f64 / rows.len() as f64);
+            self.report(&progress)
+        });
+        self.report_finish(&tracker.last());
+        if completed { Ok(()) } else { Err(Error::Cancelled) }
+    }
+
+    /// Report `progress` to the observer, if any, and return whether to go on.
+    fn report(&self, progress: &Progress) -> bool {
+        !self.is_cancelled()
+            && self.observer.borrow_mut().as_mut().is_none_or(|observer| observer.update(progress))
+    }
+
+    /// Whether the cancellation token was cancelled.
+    pub(crate) fn is_cancelled(&self) -> bool {
+        self.cancellation.is_cancelled()
+    }
+
+    /// Pixels to sample: the whole image, or the crop region with a margin wide enough for
+    /// the pixels on its edges to get the splats of their neighbours outside of it.
+    fn window(&self, scene: &Scene) -> Region {
+        match self.crop {
+            Some((region, _)) => {
+                // Samples reach the centers of the pixels within the radius of the filter.
+                let margin = (self.filter.radius() - 0.5).ceil().max(0.0) as usize;
+                let (x, y) = (region.x.saturating_sub(margin), region.y.saturating_sub(margin));
+                let right = (region.x + region.width).saturating_add(margin).min(scene.width);
+                let bottom = (region.y + region.height).saturating_add(margin).min(scene.height);
+                Region::new(x, y, right - x, bottom - y)
+            },
+            None => Region::new(0, 0, scene.width, scene.height)
+        }
+    }
+
+    /// Film of the crop region, or `film` itself if there's none.
+    fn crop_film(&self, film: Film) -> Film {
+        match self.crop {
+            Some((region, keep_size)) => film.crop(region, keep_size),
+            None => film
+        }
+    }
+
+    fn report_finish(&self, progress: &Progress) {
+        if let Some(observer) = self.observer.borrow_mut().as_mut() {
+            observer.finish(progress);
+        }
+    }
+
+    /// Reset the statistics and validate the scene ahead of a render.
+    pub(crate) fn start(&self, scene: &Scene) -> Result<()> {
+        let start_time = Instant::now();
+        let mut stats = RenderStats::default();
+        // Drop the intersection tests made on this thread outside of renders.
+        stats::take_intersection_tests();
+        for object in scene.objects.iter() {
+            match stats.objects.iter_mut().find(|(kind, _)| *kind == object.kind()) {
+                Some((_, count)) => *count += 1,
+                None => stats.objects.push((object.kind(), 1))
+            }
+        }
+        let valid = scene.validate();
+        stats.add_phase("validate", start_time.elapsed());
+        self.stats.replace(stats);
+        valid?;
+        self.filter.validate().map_err(Error::InvalidFilter)?;
+        self.validate().map_err(Error::InvalidSettings)?;
+        match self.crop {
+            Some((region, _)) if !region.fits(scene.width, scene.height) => Err(Error::InvalidCrop),
+            _ => Ok(())
+        }
+    }
+
+    /// Describe the settings that would leave the image black or make adaptive sampling meaningless, if any.
+    fn validate(&self) -> std::result::Result<(), String> {
+        if self.rays_per_pixel == 0 {
+            return Err(String::from("rays per pixel must be at least 1"));
+        }
+        match self.adaptive {
+            Some((threshold, _)) if threshold < 0.0 || threshold.is_nan() => {
+                Err(format!("adaptive sampling threshold must not be negative, not {}", threshold))
+            },
+            Some((_, max_rays_per_pixel)) if max_rays_per_pixel < self.rays_per_pixel => Err(format!(
+                "adaptive sampling maximum of {} rays per pixel is below the minimum of {}", max_rays_per_pixel, self.rays_per_pixel
+            )),
+            _ => Ok(())
+        }
+    }
+
+    /// Checkpoint of these settings at the start of a progressive render of the pixels of `region`.
+    fn checkpoint(&self, region: Region) -> Checkpoint {
+        Checkpoint {
+            seed: self.seed,
+            max_depth: self.max_depth,
+            rays_per_pixel: self.rays_per_pixel,
+            max_rays_per_pixel: self.max_rays_per_pixel(),
+            adaptive_threshold: self.adaptive.map(|(threshold, _)| threshold),
+            sampler: self.sampler,
+            filter: self.filter,
+            region,
+            samples: 0..1.min(self.max_rays_per_pixel()),
+            row: region.y
+        }
+    }
+
+    /// Save the image of `film` and its checkpoint, if `settings` asks for one.
+    fn save_progress(&self, film: &Film, settings: &Progressive, checkpoint: &Checkpoint) -> io::Result<()> {
+        let start_time = Instant::now();
+        let image = match self.crop {
+            Some((region, keep_size)) => Self::develop(&film.crop(region, keep_size)),
+            None => Self::develop(film)
+        };
+        image.save(&settings.path)?;
+        if let Some(path) = &settings.checkpoint {
+            checkpoint.save(path, film)?;
+        }
+        self.stats.borrow_mut().add_phase("save", start_time.elapsed());
+        Ok(())
+    }
+
+    /// Most rays traced for a pixel.
+    pub(crate) fn max_rays_per_pixel(&self) -> usize {
+        match self.adaptive {
+            Some((_, max_rays_per_pixel)) => max_rays_per_pixel.max(self.rays_per_pixel),
+            None => self.rays_per_pixel
+        }
+    }
+
+    /// Trace the samples of indices `samples` of every pixel of `pixels` into `film`, skipping the pixels adaptive
+    /// sampling deems precise enough. `on_row` is called with the film after each row and returns
+    /// whether to go on. Return false if the render was stopped before the last row.
+    pub(crate) fn render_samples(&self, scene: &Scene, film: &mut Film, mut aovs: Option<&mut Aovs>, samples: Range<usize>, pixels: Region, on_row: &mut dyn FnMut(usize, &Film) -> bool) -> bool {
         let width = scene.width;
         let height = scene.height;
-        let camera = scene.camera;
+        let camera = &scene.camera;
 
         let aspect_ratio = width as f64 / height as f64;
 
@@ -61,49 +423,497 @@ impl RenderEngine {
         let y1 =  1.0 / aspect_ratio;
         let dy = (y1 - y0) / (height - 1) as f64;
 
-        let mut pixels = Image::new(scene.width, scene.height);
-
-        let mut x: f64;
-        let mut y: f64;
-
-        for j in 0..height {
-            // y = y0 + (height - j - 1) as f64 * dy;
-            for i in 0..width {
-                // x = x0 + i as f64 * dx;
-                let mut color = Color::new(0.0, 0.0, 0.0);
-                for _ in 0..self.rays_per_pixel {
-                    y = y0 + (utils::random_double(0.0, 1.0) + (height - j - 1) as f64) * dy;
-                    x = x0 + (utils::random_double(0.0, 1.0) + i as f64) * dx;
-                    let ray = Ray::new(camera, Vector::new(x, y, -1.0) - camera);
-                    color += self.ray_trace(ray, scene, self.max_depth);
+        let mut sampler = self.sampler.create(self.max_rays_per_pixel(), self.seed);
+
+        // Materials get their ID from the first camera ray hitting them, so that the materials
+        // of the children of CSG nodes and instances get one too.
+        let mut materials: Vec<Rc<dyn Material>> = Vec::new();
+
+        for j in pixels.rows() {
+            let row_start = Instant::now();
+            let mut aov_time = Duration::ZERO;
+            for i in pixels.columns() {
+                for s in samples.clone() {
+                    if self.converged(film, i, j) {
+                        break;
+                    }
+
+                    sampler.start_sample(i, j, s);
+                    // Position of the sample on the film, in pixels from the top-left corner.
+                    let (u, v) = sampler.next_2d();
+                    let (film_x, film_y) = (i as f64 + u, j as f64 + v);
+
+                    let x = x0 + film_x * dx;
+                    let y = y0 + (height as f64 - film_y) * dy;
+                    let ray = camera.get_ray(x, y, sampler.next_1d());
+                    self.stats.borrow_mut().primary_rays += 1;
+                    let (direct, indirect, hit_record) = self.trace(ray, scene, self.max_depth, sampler.as_mut());
+                    film.add_sample(film_x, film_y, direct + indirect);
+
+                    if let Some(aovs) = aovs.as_deref_mut() {
+                        let aov_start = Instant::now();
+                        let mut sample = AovSample { direct, indirect, ..AovSample::default() };
+                        if let Some((object_id, hit_record)) = hit_record {
+                            let material = hit_record.material();
+                            sample.albedo = material.get_albedo(&hit_record);
+                            sample.normal = hit_record.normal;
+                            sample.depth = hit_record.distance;
+                            sample.position = hit_record.position;
+                            sample.object_id = Some(object_id);
+                            sample.material_id = match materials.iter().position(|other| Rc::ptr_eq(other, &material)) {
+                                Some(id) => Some(id),
+                                None => {
+                                    materials.push(material);
+                                    Some(materials.len() - 1)
+                                }
+                            };
+                        }
+                        aovs.add_sample(i, j, &sample);
+                        aov_time += aov_start.elapsed();
+                    }
+                }
+            }
+            {
+                let mut stats = self.stats.borrow_mut();
+                stats.add_phase("trace", row_start.elapsed().saturating_sub(aov_time));
+                if aovs.is_some() {
+                    stats.add_phase("aovs", aov_time);
                 }
-                pixels.set_pixel(i, j, (color / self.rays_per_pixel as f64).powf(0.5));
+                stats.add_intersection_tests(stats::take_intersection_tests());
+            }
+            if !on_row(j, film) {
+                return false;
+            }
+        }
+        true
+    }
+
+    /// Whether adaptive sampling has taken enough samples of pixel `(x, y)`.
+    fn converged(&self, film: &Film, x: usize, y: usize) -> bool {
+        match self.adaptive {
+            Some((threshold, _)) => film.sample_count(x, y) >= self.rays_per_pixel && film.standard_error(x, y) <= threshold,
+            None => false
+        }
+    }
+
+    /// Gamma-corrected image of the film.
+    pub fn develop(film: &Film) -> Image {
+        let mut pixels = Image::new(film.width(), film.height());
+        for j in 0..film.height() {
+            for i in 0..film.width() {
+                pixels.set_pixel(i, j, film.get_pixel(i, j).powf(0.5));
             }
-            eprint!("\r> {} %", j * 100 / (height - 1));
         }
-        eprintln!();
         pixels
     }
 
-    fn ray_trace(&self, ray: Ray, scene: &Scene, depth: isize) -> Color {
+    /// Render each of `frames` at `fps` into the numbered files of `path`, as formatted by
+    /// `animation::frame_path`. `build_scene` returns the scene at a given time in seconds.
+    /// `on_frame` is called with each frame and its path before the frame is rendered.
+    pub fn render_animation<F: Fn(f64) -> Result<Scene>>(&self, frames: Range<usize>, fps: f64, build_scene: F, path: &str, on_frame: &mut dyn FnMut(usize, &str)) -> Result<()> {
+        for frame in frames {
+            let scene = build_scene(frame as f64 / fps)?;
+            let frame_path = animation::frame_path(path, frame);
+            on_frame(frame, &frame_path);
+            self.render(&scene)?.save(frame_path)?;
+        }
+        Ok(())
+    }
+
+    fn ray_trace(&self, ray: Ray, scene: &Scene, depth: isize, sampler: &mut dyn Sampler) -> Color {
+        let (direct, indirect, _) = self.trace(ray, scene, depth, sampler);
+        direct + indirect
+    }
+
+    /// Trace `ray` and return the light it brings back, split into the light scattered at its first
+    /// interaction and the light from further along the path, along with the surface hit, if any,
+    /// and the index of its object in the scene. Paths scattering in a medium hit no surface.
+    fn trace(&self, ray: Ray, scene: &Scene, depth: isize, sampler: &mut dyn Sampler) -> (Color, Color, Option<(usize, HitRecord)>) {
         // If we've exceeded the ray bounce limit, no more light is gathered.
         if depth <= 0 {
-            return Color::new(0.0, 0.0, 0.0);
+            return (Color::new(0.0, 0.0, 0.0), Color::new(0.0, 0.0, 0.0), None);
         }
 
+        if depth < self.max_depth {
+            self.stats.borrow_mut().secondary_rays += 1;
+        }
         let hit_record = self.find_nearest(ray, scene, 0.0001, utils::INFINITY);
 
-        if matches!(hit_record, None) {
-            // return Color::new(0.0, 0.0, 0.0);
-            let t = 0.5 * (ray.direction.normalize().1 + 1.0);
-            return (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
+        // Light may scatter in a medium before reaching the surface.
+        let t_surface = hit_record.as_ref().map_or(utils::INFINITY, |(_, hit_record)| hit_record.distance);
+        let (weight, event) = self.sample_media(ray, scene, t_surface, sampler);
+        if let Some((distance, phase)) = event {
+            let (direct, indirect) = self.scatter_in_medium(ray, distance, phase, scene, depth, sampler);
+            return (weight * direct, weight * indirect, None);
         }
 
-        let hit_record = hit_record.unwrap();
-        let material = hit_record.object.get_material();
-        let scattered = material.scatter(&hit_record);
+        let (index, hit_record) = match hit_record {
+            Some(hit) => hit,
+            None => {
+                let t = 0.5 * (ray.direction.normalize().1 + 1.0);
+                let sky = (1.0 - t) * Color::new(1.0, 1.0, 1.0) + t * Color::new(0.5, 0.7, 1.0);
+                return (weight * sky, Color::new(0.0, 0.0, 0.0), None);
+            }
+        };
+
+        let material = hit_record.material();
+        let scattered = material.scatter(&hit_record, sampler);
         let attenuation = material.get_attenuation(&hit_record);
-        material.color_at(&hit_record, scene)
-        + (self.ray_trace(scattered, scene, depth - 1) * attenuation)
+        let direct = weight * material.color_at(&hit_record, scene);
+        let indirect = weight * (self.ray_trace(scattered, scene, depth - 1, sampler) * attenuation);
+        (direct, indirect, Some((index, hit_record)))
+    }
+
+    /// Sample the nearest scattering event of `ray` in the fog and volumes of the scene before `t_max`.
+    /// Return the weight of the sample and the distance and phase function of the event, if any.
+    fn sample_media(&self, ray: Ray, scene: &Scene, t_max: f64, sampler: &mut dyn Sampler) -> (Color, Option<(f64, HenyeyGreenstein)>) {
+        let t_min = 0.0001;
+        // Nearest event with its distance, weight, phase function and the index of its volume, the fog being none.
+        let mut nearest: Option<(f64, Color, HenyeyGreenstein, Option<usize>)> = None;
+
+        // Collisions are drawn independently for each medium and the nearest one wins.
+        if let Some(fog) = scene.fog {
+            if let VolumeSample::Scatter { distance, weight } = fog.sample(t_max - t_min, sampler.next_1d()) {
+                nearest = Some((t_min + distance, weight, fog.phase, None));
+            }
+        }
+        for (i, volume) in scene.volumes.iter().enumerate() {
+            if let VolumeSample::Scatter { distance, weight } = volume.sample(ray, t_min, t_max, sampler) {
+                if nearest.is_none_or(|(nearest, _, _, _)| distance < nearest) {
+                    nearest = Some((distance, weight, volume.phase(), Some(i)));
+                }
+            }
+        }
+
+        // The other media are weighted for passing up to the event, not over their whole length.
+        let (end, mut weight, winner) = match nearest {
+            Some((distance, weight, _, winner)) => (distance, weight, Some(winner)),
+            None => (t_max, Color::new(1.0, 1.0, 1.0), None)
+        };
+        if let (Some(fog), false) = (scene.fog, winner == Some(None)) {
+            weight = weight * fog.pass_weight(end - t_min);
+        }
+        for (i, volume) in scene.volumes.iter().enumerate() {
+            if winner != Some(Some(i)) {
+                weight = weight * volume.pass_weight(ray, t_min, end);
+            }
+        }
+        (weight, nearest.map(|(distance, _, phase, _)| (distance, phase)))
+    }
+
+    /// Gather light scattered towards the origin of `ray` by a medium at `distance` along it,
+    /// split into the light coming straight from the lights and the light scattered further.
+    fn scatter_in_medium(&self, ray: Ray, distance: f64, phase: HenyeyGreenstein, scene: &Scene, depth: isize, sampler: &mut dyn Sampler) -> (Color, Color) {
+        let position = ray.origin + ray.direction * distance;
+        let mut color = Color::new(0.0, 0.0, 0.0);
+
+        // Direct light, attenuated by the media on the way. The phase function is
+        // scaled so that scattering evenly in all directions has a weight of one.
+        for light in scene.lights.iter() {
+            let to_light = Ray::with_time(position, light.position - position, ray.time);
+            let light_distance = (light.position - position).magnitude();
+            self.stats.borrow_mut().shadow_rays += 1;
+            color += 4.0 * utils::PI * phase.evaluate(ray.direction.dot(to_light.direction))
+                * self.transmittance(to_light, scene, light_distance, sampler)
+                * light.color;
+        }
+
+        // Indirect light, importance sampled from the phase function.
+        let (u1, u2) = sampler.next_2d();
+        let direction = phase.sample(ray.direction, u1, u2);
+        (color, self.ray_trace(Ray::with_time(position, direction, ray.time), scene, depth - 1, sampler))
+    }
+
+    /// Fraction of light transmitted through the media of the scene along `ray` up to `t_max`.
+    fn transmittance(&self, ray: Ray, scene: &Scene, t_max: f64, sampler: &mut dyn Sampler) -> Color {
+        let t_min = 0.0001;
+        let mut transmittance = match scene.fog {
+            Some(fog) => fog.transmittance(t_max - t_min),
+            None => Color::new(1.0, 1.0, 1.0)
+        };
+        for volume in scene.volumes.iter() {
+            transmittance = transmittance * volume.transmittance(ray, t_min, t_max, sampler);
+        }
+        transmittance
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{aov::Aov, camera::Camera, light::Light, material::Dielectric, object::{AxisAlignedBox, Csg, Object, Sphere}, point::Point, volume::{ConstantMedium, Medium}};
+
+    fn scene() -> Scene {
+        let material = Rc::new(Dielectric::new(Color::new(1.0, 1.0, 1.0), 1.5, 0.0, 0.0, 0.0, 0.0));
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, -1.0), 0.5, material));
+        let light = Light::new(Point::new(0.0, 2.0, 0.0), Color::new(1.0, 1.0, 1.0));
+        let mut scene = Scene::new(Camera::new(Point::new(0.0, 0.0, 0.0)), vec![sphere], vec![light], 8, 6);
+        scene.fog = Some(Medium::new(Color::new(0.1, 0.1, 0.1), Color::new(0.2, 0.2, 0.2), 0.3));
+        scene
+    }
+
+    fn render(engine: &RenderEngine) -> Vec<u64> {
+        let image = engine.render(&scene()).unwrap();
+        let mut bits = Vec::new();
+        for y in 0..6 {
+            for x in 0..8 {
+                let pixel = image.get_pixel(x, y);
+                bits.extend_from_slice(&[pixel.0.to_bits(), pixel.1.to_bits(), pixel.2.to_bits()]);
+            }
+        }
+        bits
+    }
+
+    #[test]
+    fn same_seed_renders_identical_images() {
+        for sampler in [SamplerKind::Random, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol] {
+            let mut engine = RenderEngine::new(5, 4);
+            engine.set_sampler(sampler);
+            engine.set_seed(7);
+            assert_eq!(render(&engine), render(&engine));
+
+            let first = render(&engine);
+            engine.set_seed(8);
+            assert_ne!(first, render(&engine));
+        }
+    }
+
+    #[test]
+    fn degenerate_scene_fails_to_render() {
+        let mut scene = scene();
+        scene.width = 1;
+        let material = Rc::new(Dielectric::new(Color::new(1.0, 1.0, 1.0), 1.5, 0.0, 0.0, 0.0, 0.0));
+        scene.objects.push(Rc::new(Sphere::new(Point::new(0.0, 0.0, -2.0), 0.0, material)));
+
+        match RenderEngine::new(5, 1).render(&scene) {
+            Err(Error::InvalidScene(problems)) => assert_eq!(problems.len(), 2),
+            _ => panic!("degenerate scene rendered")
+        }
+    }
+
+    #[test]
+    fn stats_count_rays_of_the_last_render() {
+        let engine = RenderEngine::new(5, 4);
+        engine.render(&scene()).unwrap();
+        let stats = engine.stats();
+        assert_eq!(stats.primary_rays, 8 * 6 * 4);
+        assert!(stats.secondary_rays > 0 && stats.shadow_rays > 0);
+        assert_eq!(stats.intersection_tests, vec![("sphere", stats.primary_rays + stats.secondary_rays)]);
+        assert!(stats.phases.iter().all(|(phase, _)| *phase != "aovs"));
+    }
+
+    #[test]
+    fn stats_count_intersection_tests_of_csg_children_and_volume_boundaries() {
+        let material = Rc::new(Dielectric::default());
+        let left: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(-0.3, 0.0, -1.5), 0.4, material.clone()));
+        let right: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.3, 0.0, -1.5), 0.4, material.clone()));
+        let csg: Rc<dyn Object> = Rc::new(Csg::union(left, right));
+        let boundary: Rc<dyn Object> = Rc::new(AxisAlignedBox::new(Point::new(-1.0, -1.0, -3.0), Point::new(1.0, 1.0, -2.0), material));
+        let mut scene = Scene::new(Camera::new(Point::new(0.0, 0.0, 0.0)), vec![csg], vec![], 16, 8);
+        scene.volumes.push(Rc::new(ConstantMedium::new(boundary, Medium::new(Color::new(0.1, 0.1, 0.1), Color::new(0.5, 0.5, 0.5), 0.0))));
+
+        let engine = RenderEngine::new(3, 2);
+        engine.render_with_aovs(&scene).unwrap();
+        let stats = engine.stats();
+        let tests = |kind| stats.intersection_tests.iter().find(|(name, _)| *name == kind).map_or(0, |(_, count)| *count);
+        // Every nearest hit search tests the CSG node, which tests both of its children at least once.
+        assert_eq!(tests("csg"), stats.primary_rays + stats.secondary_rays);
+        assert!(tests("sphere") >= 2 * tests("csg"), "{:?}", stats.intersection_tests);
+        assert!(tests("box") > 0, "{:?}", stats.intersection_tests);
+        assert!(stats.phases.iter().any(|(phase, _)| *phase == "aovs"));
+    }
+
+    #[test]
+    fn paths_scattering_in_a_medium_hit_no_surface() {
+        let material = Rc::new(Dielectric::default());
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, -3.0), 1.0, material.clone()));
+        let boundary: Rc<dyn Object> = Rc::new(AxisAlignedBox::new(Point::new(-10.0, -10.0, -1.5), Point::new(10.0, 10.0, -0.5), material));
+        let mut scene = Scene::new(Camera::new(Point::new(0.0, 0.0, 0.0)), vec![sphere], vec![], 8, 6);
+        scene.volumes.push(Rc::new(ConstantMedium::new(boundary, Medium::new(Color::new(0.0, 0.0, 0.0), Color::new(1E6, 1E6, 1E6), 0.0))));
+
+        let (_, aovs) = RenderEngine::new(2, 1).render_with_aovs(&scene).unwrap();
+        for y in 0..6 {
+            for x in 0..8 {
+                let id = aovs.get_pixel(Aov::ObjectId, x, y);
+                assert_eq!(id.0, 0.0, "object behind the medium seen at ({}, {})", x, y);
+                let depth = aovs.get_pixel(Aov::Depth, x, y);
+                assert_eq!(depth.0, 0.0, "surface depth behind the medium at ({}, {})", x, y);
+            }
+        }
+    }
+
+    #[test]
+    fn observer_follows_and_cancels_render() {
+        let rows = Rc::new(RefCell::new(Vec::new()));
+        let mut engine = RenderEngine::new(5, 1);
+        let seen = Rc::clone(&rows);
+        engine.set_observer(move |progress: &Progress| {
+            seen.borrow_mut().push((progress.rows_done, progress.fraction));
+            progress.rows_done < 3
+        });
+
+        assert!(matches!(engine.render(&scene()), Err(Error::Cancelled)));
+        assert_eq!(*rows.borrow(), vec![(1, 1.0 / 6.0), (2, 2.0 / 6.0), (3, 3.0 / 6.0)]);
+    }
+
+    #[test]
+    fn crop_renders_the_same_pixels_as_the_whole_image() {
+        let region = Region::new(2, 1, 3, 4);
+        for (filter, keep_size) in [(Filter::default(), false), (Filter::default(), true), (Filter::Tent { radius: 1.7 }, false)] {
+            let mut engine = RenderEngine::new(5, 2);
+            engine.set_filter(filter);
+            let full = engine.render(&scene()).unwrap();
+            engine.set_crop(region, keep_size);
+            let cropped = engine.render(&scene()).unwrap();
+            let (x0, y0) = if keep_size { (0, 0) } else { (region.x, region.y) };
+            assert_eq!((cropped.width(), cropped.height()), if keep_size { (8, 6) } else { (3, 4) });
+            for y in 0..cropped.height() {
+                for x in 0..cropped.width() {
+                    let inside = region.columns().contains(&(x + x0)) && region.rows().contains(&(y + y0));
+                    let expected = if inside { full.get_pixel(x + x0, y + y0) } else { Color::default() };
+                    let pixel = cropped.get_pixel(x, y);
+                    assert_eq!((pixel.0, pixel.1, pixel.2), (expected.0, expected.1, expected.2));
+                }
+            }
+        }
+    }
+
+    /// Progressive settings saving into the temporary directory under `name`.
+    fn progressive(name: &str, resume: bool) -> Progressive {
+        let path = std::env::temp_dir().join(format!("ray_tracer_{}_{}", std::process::id(), name));
+        Progressive {
+            path: format!("{}.ppm", path.display()),
+            interval: Duration::from_secs(3600),
+            budget: None,
+            checkpoint: Some(format!("{}.checkpoint", path.display())),
+            resume
+        }
+    }
+
+    fn remove(settings: &Progressive) {
+        let _ = std::fs::remove_file(&settings.path);
+        let _ = std::fs::remove_file(settings.checkpoint.as_ref().unwrap());
+    }
+
+    fn pixels(film: &Film) -> Vec<(f64, f64, f64)> {
+        let mut pixels = Vec::new();
+        for y in 0..film.height() {
+            for x in 0..film.width() {
+                let pixel = film.get_pixel(x, y);
+                pixels.push((pixel.0, pixel.1, pixel.2));
+            }
+        }
+        pixels
+    }
+
+    #[test]
+    fn resumed_render_matches_uninterrupted_render() {
+        let mut engine = RenderEngine::new(5, 8);
+        engine.set_adaptive(0.01, 16);
+        let whole = engine.render_progressive(&scene(), &progressive("whole", false)).unwrap();
+        remove(&progressive("whole", false));
+
+        // Stop in the middle of the pass of 2 to 4 samples per pixel, then resume.
+        let updates = Rc::new(RefCell::new(0));
+        let counted = Rc::clone(&updates);
+        engine.set_observer(move |_: &Progress| {
+            *counted.borrow_mut() += 1;
+            *counted.borrow() < 6 + 6 + 3
+        });
+        let interrupted = engine.render_progressive(&scene(), &progressive("resumed", false)).unwrap();
+        assert_ne!(pixels(&interrupted), pixels(&whole));
+
+        let resumed_at = Rc::new(RefCell::new(None));
+        let first = Rc::clone(&resumed_at);
+        engine.set_observer(move |progress: &Progress| {
+            first.borrow_mut().get_or_insert((progress.samples_per_pixel, progress.rows_done));
+            true
+        });
+        let resumed = engine.render_progressive(&scene(), &progressive("resumed", true)).unwrap();
+        remove(&progressive("resumed", true));
+        assert_eq!(*resumed_at.borrow(), Some((4, 3)));
+        assert_eq!(pixels(&resumed), pixels(&whole));
+    }
+
+    #[test]
+    fn checkpoint_of_other_settings_is_rejected() {
+        let settings = progressive("settings", false);
+        let mut engine = RenderEngine::new(5, 2);
+        engine.render_progressive(&scene(), &settings).unwrap();
+
+        engine.set_sampler(SamplerKind::Halton);
+        let resumed = engine.render_progressive(&scene(), &progressive("settings", true));
+        remove(&settings);
+        assert!(matches!(resumed, Err(Error::CheckpointMismatch)));
+    }
+
+    #[test]
+    fn checkpoint_of_other_crop_is_rejected() {
+        let settings = progressive("crop", false);
+        let mut engine = RenderEngine::new(5, 2);
+        engine.set_crop(Region::new(0, 4, 8, 2), false);
+        engine.render_progressive(&scene(), &settings).unwrap();
+
+        // The rows of the checkpoint lie past the end of this region.
+        engine.set_crop(Region::new(0, 0, 8, 2), false);
+        let resumed = engine.render_progressive(&scene(), &progressive("crop", true));
+        remove(&settings);
+        assert!(matches!(resumed, Err(Error::CheckpointMismatch)));
+    }
+
+    #[test]
+    fn aovs_identify_objects_and_materials_of_csg_children() {
+        let (left_material, right_material) = (Rc::new(Dielectric::default()), Rc::new(Dielectric::default()));
+        let left: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(-0.3, 0.0, -1.5), 0.4, left_material));
+        let right: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.3, 0.0, -1.5), 0.4, right_material));
+        let csg: Rc<dyn Object> = Rc::new(Csg::union(left, right));
+        let scene = Scene::new(Camera::new(Point::new(0.0, 0.0, 0.0)), vec![csg], vec![], 16, 8);
+
+        let (_, aovs) = RenderEngine::new(2, 1).render_with_aovs(&scene).unwrap();
+        let mut objects = Vec::new();
+        let mut materials = Vec::new();
+        for y in 0..8 {
+            for x in 0..16 {
+                objects.push(aovs.get_pixel(Aov::ObjectId, x, y).0);
+                materials.push(aovs.get_pixel(Aov::MaterialId, x, y).0);
+            }
+        }
+        assert!(objects.iter().all(|id| *id == 0.0 || *id == 1.0) && objects.contains(&1.0));
+        // The left child is hit first, on the left of the image.
+        assert!(materials.contains(&1.0) && materials.contains(&2.0));
+        assert!(materials.iter().all(|id| *id <= 2.0));
+        assert_eq!(materials[4 * 16 + 5], 1.0);
+        assert_eq!(materials[4 * 16 + 10], 2.0);
+    }
+
+    #[test]
+    fn invalid_settings_fail_to_render() {
+        let engine = RenderEngine::new(5, 0);
+        assert!(matches!(engine.render(&scene()), Err(Error::InvalidSettings(_))));
+
+        let mut engine = RenderEngine::new(5, 8);
+        engine.set_adaptive(0.01, 4);
+        assert!(matches!(engine.render_film(&scene()), Err(Error::InvalidSettings(_))));
+        engine.set_adaptive(f64::NAN, 16);
+        assert!(matches!(engine.render_timed(&scene(), Duration::from_secs(1)), Err(Error::InvalidSettings(_))));
+        engine.set_adaptive(0.01, 8);
+        assert!(engine.render(&scene()).is_ok());
+    }
+
+    #[test]
+    fn degenerate_filter_fails_to_render() {
+        let mut engine = RenderEngine::new(5, 1);
+        engine.set_filter(Filter::Mitchell { radius: 0.0, b: 1.0 / 3.0, c: 1.0 / 3.0 });
+        assert!(matches!(engine.render(&scene()), Err(Error::InvalidFilter(_))));
+    }
+
+    #[test]
+    fn cancelled_token_stops_render() {
+        let mut engine = RenderEngine::new(5, 2);
+        let token = CancellationToken::new();
+        engine.set_cancellation(token.clone());
+        token.cancel();
+        assert!(matches!(engine.render(&scene()), Err(Error::Cancelled)));
     }
 }
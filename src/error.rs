@@ -0,0 +1,58 @@
+use std::fmt;
+use std::io;
+
+
+/// Error of the renderer.
+#[derive(Debug)]
+pub enum Error {
+    Io(io::Error),
+    /// Problems found by `Scene::validate`, one per degenerate part of the scene.
+    InvalidScene(Vec<String>),
+    /// The checkpoint to resume was saved by a render of other settings or size.
+    CheckpointMismatch,
+    /// No material of this name was added to the `MaterialLibrary`.
+    UnknownMaterial(String),
+    /// The crop region doesn't fit in the image.
+    InvalidCrop,
+    /// The reconstruction filter has a radius or parameter that makes its weights meaningless.
+    InvalidFilter(String),
+    /// The settings of the engine, like zero rays per pixel, can't make an image.
+    InvalidSettings(String),
+    /// The progress observer or the cancellation token cancelled the render.
+    Cancelled,
+    /// The preview window failed to open or update.
+    Window(String)
+}
+
+pub type Result<T> = std::result::Result<T, Error>;
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Error::Io(error) => write!(f, "{}", error),
+            Error::InvalidScene(problems) => write!(f, "invalid scene: {}", problems.join("; ")),
+            Error::CheckpointMismatch => write!(f, "checkpoint doesn't match the render settings"),
+            Error::UnknownMaterial(name) => write!(f, "no material named \"{}\"", name),
+            Error::InvalidCrop => write!(f, "crop region lies outside of the image"),
+            Error::InvalidFilter(problem) => write!(f, "invalid filter: {}", problem),
+            Error::InvalidSettings(problem) => write!(f, "invalid render settings: {}", problem),
+            Error::Cancelled => write!(f, "render cancelled"),
+            Error::Window(error) => write!(f, "preview window: {}", error)
+        }
+    }
+}
+
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io(error) => Some(error),
+            _ => None
+        }
+    }
+}
+
+impl From<io::Error> for Error {
+    fn from(error: io::Error) -> Self {
+        Error::Io(error)
+    }
+}
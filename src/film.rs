@@ -0,0 +1,226 @@
+use std::io::{self, Read, Write};
+
+use crate::{
+    color::Color,
+    filter::Filter,
+    image::{Image, Region},
+    utils
+};
+
+
+/// Film accumulates the filtered samples of a render. Each sample is splatted into
+/// every pixel within the radius of the filter and a pixel is the weighted average of them.
+pub struct Film {
+    width: usize,
+    height: usize,
+    filter: Filter,
+    /// Weighted sum of the samples of each pixel.
+    sum: Vec<Color>,
+    /// Sum of the weights of each pixel.
+    weight: Vec<f64>,
+    /// Statistics of the samples taken inside each pixel.
+    stats: Vec<Variance>
+}
+
+impl Film {
+    pub fn new(width: usize, height: usize, filter: Filter) -> Self {
+        Self {
+            width,
+            height,
+            filter,
+            sum: vec![Color::default(); width * height],
+            weight: vec![0.0; width * height],
+            stats: vec![Variance::default(); width * height]
+        }
+    }
+
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
+    /// Add a sample at `(x, y)` in pixel units, with `x` growing to the right, `y` growing
+    /// downwards and the center of pixel `(i, j)` at `(i + 0.5, j + 0.5)`.
+    pub fn add_sample(&mut self, x: f64, y: f64, color: Color) {
+        let radius = self.filter.radius();
+        // Pixels whose center lies in `(x - radius, x + radius]`, and likewise for `y`.
+        let (i0, i1) = Self::pixel_range(x, radius, self.width);
+        let (j0, j1) = Self::pixel_range(y, radius, self.height);
+
+        // Adaptive sampling looks at the error of the pixels as displayed, after gamma correction.
+        let (i, j) = (x.max(0.0) as usize, y.max(0.0) as usize);
+        if i < self.width && j < self.height {
+            self.stats[j * self.width + i].add(luminance(color.powf(0.5)));
+        }
+
+        for j in j0..j1 {
+            for i in i0..i1 {
+                let weight = self.filter.evaluate(i as f64 + 0.5 - x, j as f64 + 0.5 - y);
+                if weight != 0.0 {
+                    let index = j * self.width + i;
+                    self.sum[index] += weight * color;
+                    self.weight[index] += weight;
+                }
+            }
+        }
+    }
+
+    /// Filtered color of pixel `(x, y)`, black if no sample reached it.
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        let index = y * self.width + x;
+        if self.weight[index] == 0.0 {
+            Color::default()
+        } else {
+            self.sum[index] / self.weight[index]
+        }
+    }
+
+    pub fn sample_count(&self, x: usize, y: usize) -> usize {
+        self.stats[y * self.width + x].count
+    }
+
+    /// Mean number of samples taken per pixel.
+    pub fn samples_per_pixel(&self) -> f64 {
+        self.stats.iter().map(|stats| stats.count).sum::<usize>() as f64 / self.stats.len().max(1) as f64
+    }
+
+    /// Standard error of the mean displayed luminance of the samples taken in pixel `(x, y)`.
+    pub fn standard_error(&self, x: usize, y: usize) -> f64 {
+        self.stats[y * self.width + x].standard_error()
+    }
+
+    /// False-color image of the number of samples taken in each pixel,
+    /// from blue for the fewest through green to red for the most.
+    pub fn heatmap(&self) -> Image {
+        let min = self.stats.iter().map(|stats| stats.count).min().unwrap_or(0);
+        let max = self.stats.iter().map(|stats| stats.count).max().unwrap_or(0);
+        let range = (max - min).max(1) as f64;
+
+        let mut image = Image::new(self.width, self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let t = (self.sample_count(x, y) - min) as f64 / range;
+                let color = if t < 0.5 {
+                    Color::new(0.0, 2.0 * t, 1.0 - 2.0 * t)
+                } else {
+                    Color::new(2.0 * t - 1.0, 2.0 - 2.0 * t, 0.0)
+                };
+                image.set_pixel(x, y, color);
+            }
+        }
+        image
+    }
+
+    /// Copy of the samples of the pixels of `region`, into a film of the size of the region,
+    /// or of the same size as this one with nothing outside of the region if `keep_size` is set.
+    pub fn crop(&self, region: Region, keep_size: bool) -> Film {
+        let (x0, y0) = if keep_size { (0, 0) } else { (region.x, region.y) };
+        let mut film = if keep_size {
+            Film::new(self.width, self.height, self.filter)
+        } else {
+            Film::new(region.width, region.height, self.filter)
+        };
+        for y in region.rows() {
+            for x in region.columns() {
+                let (from, to) = (y * self.width + x, (y - y0) * film.width + x - x0);
+                film.sum[to] = self.sum[from];
+                film.weight[to] = self.weight[from];
+                film.stats[to] = self.stats[from];
+            }
+        }
+        film
+    }
+
+    /// Write the accumulated samples, as little-endian binary.
+    pub fn write_to<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(&(self.width as u64).to_le_bytes())?;
+        writer.write_all(&(self.height as u64).to_le_bytes())?;
+        for index in 0..self.width * self.height {
+            let (sum, stats) = (self.sum[index], self.stats[index]);
+            for value in [sum.0, sum.1, sum.2, self.weight[index], stats.mean, stats.m2] {
+                writer.write_all(&value.to_bits().to_le_bytes())?;
+            }
+            writer.write_all(&(stats.count as u64).to_le_bytes())?;
+        }
+        Ok(())
+    }
+
+    /// Read samples written by `write_to` exactly as they were, up to the end of `reader`,
+    /// splatting the next ones with `filter`.
+    pub fn read_from<R: Read>(reader: &mut R, filter: Filter) -> io::Result<Self> {
+        // Sums, weight, mean and M2 as `f64` and the count as `u64`.
+        const PIXEL_BYTES: usize = 7 * 8;
+
+        let width = read_u64(reader)? as usize;
+        let height = read_u64(reader)? as usize;
+        // Check the size against the data there is, before trusting it with an allocation.
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if Some(bytes.len()) != width.checked_mul(height).and_then(|pixels| pixels.checked_mul(PIXEL_BYTES)) {
+            return Err(invalid_data("film size doesn't match its data"));
+        }
+
+        let mut film = Self::new(width, height, filter);
+        let mut reader = &bytes[..];
+        for index in 0..width * height {
+            let mut values = [0.0; 6];
+            for value in values.iter_mut() {
+                *value = f64::from_bits(read_u64(&mut reader)?);
+            }
+            film.sum[index] = Color::new(values[0], values[1], values[2]);
+            film.weight[index] = values[3];
+            film.stats[index] = Variance { count: read_u64(&mut reader)? as usize, mean: values[4], m2: values[5] };
+        }
+        Ok(film)
+    }
+
+    fn pixel_range(position: f64, radius: f64, size: usize) -> (usize, usize) {
+        let start = (position - radius - 0.5).floor() + 1.0;
+        let end = (position + radius - 0.5).floor() + 1.0;
+        (start.max(0.0) as usize, (end.max(0.0) as usize).min(size))
+    }
+}
+
+
+/// Running mean and variance of a series of values (Welford's algorithm).
+#[derive(Debug, Copy, Clone, Default)]
+struct Variance {
+    count: usize,
+    mean: f64,
+    m2: f64
+}
+
+impl Variance {
+    fn add(&mut self, value: f64) {
+        self.count += 1;
+        let delta = value - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (value - self.mean);
+    }
+
+    /// Standard error of the mean of the values.
+    fn standard_error(&self) -> f64 {
+        if self.count < 2 {
+            return utils::INFINITY;
+        }
+        (self.m2 / ((self.count - 1) * self.count) as f64).sqrt()
+    }
+}
+
+/// Relative luminance of a linear color.
+pub fn luminance(color: Color) -> f64 {
+    0.2126 * color.0 + 0.7152 * color.1 + 0.0722 * color.2
+}
+
+pub(crate) fn read_u64<R: Read>(reader: &mut R) -> io::Result<u64> {
+    let mut bytes = [0u8; 8];
+    reader.read_exact(&mut bytes)?;
+    Ok(u64::from_le_bytes(bytes))
+}
+
+pub(crate) fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
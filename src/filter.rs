@@ -0,0 +1,152 @@
+use crate::utils;
+
+
+/// Reconstruction filter weighing the contribution of a sample to the pixels around it.
+/// Every filter is separable and extends `radius` pixels from the sample on each axis.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Filter {
+    /// Average of the samples inside the radius; a radius of 0.5 keeps each sample in its pixel.
+    Box { radius: f64 },
+    /// Weight falling linearly to zero at the radius.
+    Tent { radius: f64 },
+    /// Gaussian of falloff `alpha`, shifted to reach zero at the radius.
+    Gaussian { radius: f64, alpha: f64 },
+    /// Mitchell-Netravali cubic; `b = c = 1/3` balances blurring against ringing.
+    Mitchell { radius: f64, b: f64, c: f64 },
+    /// Sinc windowed by a sinc stretched over the radius.
+    Lanczos { radius: f64 }
+}
+
+impl Default for Filter {
+    fn default() -> Self {
+        Filter::Box { radius: 0.5 }
+    }
+}
+
+impl Filter {
+    pub fn radius(&self) -> f64 {
+        match *self {
+            Filter::Box { radius }
+            | Filter::Tent { radius }
+            | Filter::Gaussian { radius, .. }
+            | Filter::Mitchell { radius, .. }
+            | Filter::Lanczos { radius } => radius
+        }
+    }
+
+    /// Fail if the radius isn't positive, or a parameter would give weights that aren't numbers.
+    pub fn validate(&self) -> Result<(), String> {
+        let radius = self.radius();
+        if !(radius > 0.0 && radius.is_finite()) {
+            return Err(format!("filter radius must be positive, not {}", radius));
+        }
+        match *self {
+            Filter::Gaussian { alpha, .. } if !(alpha > 0.0 && alpha.is_finite()) => {
+                Err(format!("gaussian filter falloff must be positive, not {}", alpha))
+            },
+            Filter::Mitchell { b, c, .. } if !(b.is_finite() && c.is_finite()) => {
+                Err(format!("mitchell filter parameters must be finite, not {} and {}", b, c))
+            },
+            _ => Ok(())
+        }
+    }
+
+    /// Weight of a sample at offset `(x, y)` in pixels from the center of a pixel.
+    pub fn evaluate(&self, x: f64, y: f64) -> f64 {
+        self.evaluate_1d(x) * self.evaluate_1d(y)
+    }
+
+    fn evaluate_1d(&self, x: f64) -> f64 {
+        let x = x.abs();
+        if x > self.radius() {
+            return 0.0;
+        }
+
+        match *self {
+            Filter::Box { .. } => 1.0,
+            Filter::Tent { radius } => radius - x,
+            Filter::Gaussian { radius, alpha } => ((-alpha * x * x).exp() - (-alpha * radius * radius).exp()).max(0.0),
+            Filter::Mitchell { radius, b, c } => {
+                // The cubic spans [-2, 2], stretch it over the radius.
+                let x = 2.0 * x / radius;
+                if x < 1.0 {
+                    ((12.0 - 9.0 * b - 6.0 * c) * x * x * x
+                        + (-18.0 + 12.0 * b + 6.0 * c) * x * x
+                        + (6.0 - 2.0 * b)) / 6.0
+                } else {
+                    ((-b - 6.0 * c) * x * x * x
+                        + (6.0 * b + 30.0 * c) * x * x
+                        + (-12.0 * b - 48.0 * c) * x
+                        + (8.0 * b + 24.0 * c)) / 6.0
+                }
+            },
+            Filter::Lanczos { radius } => sinc(x) * sinc(x / radius)
+        }
+    }
+}
+
+/// Normalized sinc, `sin(pi x) / (pi x)`.
+fn sinc(x: f64) -> f64 {
+    if x.abs() < 1E-5 {
+        return 1.0;
+    }
+    let x = utils::PI * x;
+    x.sin() / x
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EPSILON: f64 = 1E-12;
+
+    #[test]
+    fn weights_vanish_outside_the_radius() {
+        let filters = [
+            Filter::Box { radius: 0.5 },
+            Filter::Tent { radius: 1.0 },
+            Filter::Gaussian { radius: 1.5, alpha: 2.0 },
+            Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 },
+            Filter::Lanczos { radius: 3.0 }
+        ];
+        for filter in filters {
+            let radius = filter.radius();
+            assert!(filter.evaluate(0.0, 0.0) > 0.0, "{:?}", filter);
+            assert_eq!(filter.evaluate(radius + 0.01, 0.0), 0.0, "{:?}", filter);
+            assert_eq!(filter.evaluate(0.0, -radius - 0.01), 0.0, "{:?}", filter);
+            // Separable and symmetric.
+            assert!((filter.evaluate(0.3, -0.2) - filter.evaluate(0.3, 0.0) * filter.evaluate(0.0, 0.2) / filter.evaluate(0.0, 0.0)).abs() < EPSILON);
+        }
+    }
+
+    #[test]
+    fn weights_follow_their_profile() {
+        assert_eq!(Filter::Box { radius: 0.5 }.evaluate(0.4, -0.4), 1.0);
+        assert!((Filter::Tent { radius: 2.0 }.evaluate(0.5, 0.0) - 1.5 * 2.0).abs() < EPSILON);
+        assert!(Filter::Gaussian { radius: 1.0, alpha: 2.0 }.evaluate(1.0, 0.0).abs() < EPSILON);
+        // The Mitchell cubic is (6 - 2b) / 6 at the center and zero at the radius.
+        let mitchell = Filter::Mitchell { radius: 2.0, b: 1.0 / 3.0, c: 1.0 / 3.0 };
+        assert!((mitchell.evaluate(0.0, 0.0) - (16.0f64 / 18.0).powi(2)).abs() < EPSILON);
+        assert!(mitchell.evaluate(2.0, 0.0).abs() < EPSILON);
+        // Lanczos crosses zero at every whole pixel.
+        let lanczos = Filter::Lanczos { radius: 3.0 };
+        assert!((lanczos.evaluate(0.0, 0.0) - 1.0).abs() < EPSILON);
+        assert!(lanczos.evaluate(1.0, 0.0).abs() < EPSILON && lanczos.evaluate(2.0, 0.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn degenerate_filters_are_rejected() {
+        assert!(Filter::default().validate().is_ok());
+        for filter in [
+            Filter::Box { radius: 0.0 },
+            Filter::Tent { radius: -1.0 },
+            Filter::Lanczos { radius: f64::NAN },
+            Filter::Mitchell { radius: f64::INFINITY, b: 0.0, c: 0.5 },
+            Filter::Mitchell { radius: 2.0, b: f64::NAN, c: 0.5 },
+            Filter::Gaussian { radius: 1.0, alpha: 0.0 }
+        ] {
+            assert!(filter.validate().is_err(), "{:?} was accepted", filter);
+        }
+    }
+}
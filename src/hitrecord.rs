@@ -2,9 +2,11 @@ use crate::object::Object;
 use crate::vector::Vector;
 use crate::ray::Ray;
 use crate::point::Point;
-use crate::Rc;
+use crate::material::Material;
+use std::rc::Rc;
 
 
+#[derive(Clone)]
 pub struct HitRecord {
     pub object: Rc<dyn Object>,
     pub ray: Ray,
@@ -12,7 +14,14 @@ pub struct HitRecord {
 
     pub normal: Vector,
     pub position: Point,
-    pub is_out: bool
+    pub is_out: bool,
+
+    /// Surface coordinates in `[0, 1]`, for objects that define them.
+    pub u: f64,
+    pub v: f64,
+
+    /// Material of the surface hit when it isn't the one of `object`, like a child of a `Csg`.
+    pub material: Option<Rc<dyn Material>>
 }
 
 impl HitRecord {
@@ -23,7 +32,10 @@ impl HitRecord {
             distance: f64::default(),
             normal: Vector::default(),
             position: Point::default(),
-            is_out: bool::default()
+            is_out: bool::default(),
+            u: f64::default(),
+            v: f64::default(),
+            material: None
         }
     }
 
@@ -38,4 +50,17 @@ impl HitRecord {
         self.position = ray.origin + ray.direction * distance;
         self.is_out = is_out;
     }
-}
\ No newline at end of file
+
+    /// Material of the surface hit.
+    pub fn material(&self) -> Rc<dyn Material> {
+        match &self.material {
+            Some(material) => Rc::clone(material),
+            None => self.object.get_material()
+        }
+    }
+
+    pub fn update_uv(&mut self, u: f64, v: f64) {
+        self.u = u;
+        self.v = v;
+    }
+}
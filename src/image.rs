@@ -1,10 +1,43 @@
-use std::io::Write;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::ops::Range;
+use std::path::Path;
 
 use crate::color::Color;
 use crate::utils;
 
 
-#[derive(Debug)]
+/// Rectangle of pixels, `x` and `y` being its top-left corner.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Region {
+    pub x: usize,
+    pub y: usize,
+    pub width: usize,
+    pub height: usize
+}
+
+impl Region {
+    pub fn new(x: usize, y: usize, width: usize, height: usize) -> Self {
+        Self { x, y, width, height }
+    }
+
+    pub fn columns(&self) -> Range<usize> {
+        self.x..self.x + self.width
+    }
+
+    pub fn rows(&self) -> Range<usize> {
+        self.y..self.y + self.height
+    }
+
+    /// Whether the region is non-empty and lies inside an image of `width` by `height` pixels.
+    pub fn fits(&self, width: usize, height: usize) -> bool {
+        let fits = |start: usize, size: usize, end: usize| size > 0 && start.checked_add(size).is_some_and(|stop| stop <= end);
+        fits(self.x, self.width, width) && fits(self.y, self.height, height)
+    }
+}
+
+
+#[derive(Debug, Clone)]
 pub struct Image {
     width: usize,
     height: usize,
@@ -19,18 +52,63 @@ impl Image {
         }
     }
 
+    pub fn width(&self) -> usize {
+        self.width
+    }
+
+    pub fn height(&self) -> usize {
+        self.height
+    }
+
     pub fn set_pixel(&mut self, x: usize, y: usize, pixel: Color) {
         self.pixels[y * self.width + x] = pixel;
     }
 
-    pub fn show(&self) {
+    pub fn get_pixel(&self, x: usize, y: usize) -> Color {
+        self.pixels[y * self.width + x]
+    }
+
+    /// Write the image to the standard output as a binary PPM.
+    pub fn show(&self) -> io::Result<()> {
+        self.write_ppm(&mut io::stdout().lock())
+    }
+
+    /// Write the image to `path`, as a floating point PFM if its extension is `.pfm`
+    /// and as a binary PPM otherwise.
+    pub fn save<P: AsRef<Path>>(&self, path: P) -> io::Result<()> {
+        let is_pfm = path.as_ref().extension().is_some_and(|extension| extension == "pfm");
+        let mut writer = BufWriter::new(File::create(path)?);
+        if is_pfm {
+            self.write_pfm(&mut writer)?;
+        } else {
+            self.write_ppm(&mut writer)?;
+        }
+        writer.flush()
+    }
+
+    pub fn write_ppm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
         let mut pixels = Vec::<u8>::with_capacity(3 * self.width * self.height);
         for c in self.pixels.iter() {
             pixels.extend_from_slice(&[Self::to_byte(c.0), Self::to_byte(c.1), Self::to_byte(c.2)]);
         }
 
-        print!("P6\n{} {}\n255\n", self.width, self.height);
-        std::io::stdout().write_all(&pixels).expect("Error occurred while writing pixel data.");
+        write!(writer, "P6\n{} {}\n255\n", self.width, self.height)?;
+        writer.write_all(&pixels)
+    }
+
+    /// Write the unclamped values as little-endian 32 bit floats, rows from bottom to top.
+    pub fn write_pfm<W: Write>(&self, writer: &mut W) -> io::Result<()> {
+        let mut pixels = Vec::<u8>::with_capacity(12 * self.width * self.height);
+        for row in self.pixels.chunks(self.width).rev() {
+            for c in row.iter() {
+                for value in [c.0, c.1, c.2] {
+                    pixels.extend_from_slice(&(value as f32).to_le_bytes());
+                }
+            }
+        }
+
+        write!(writer, "PF\n{} {}\n-1.0\n", self.width, self.height)?;
+        writer.write_all(&pixels)
     }
 
     #[inline]
@@ -38,3 +116,24 @@ impl Image {
         (256.0 * utils::clamp(value, 0.0, 0.999)) as u8
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn region_fits_inside_the_image() {
+        assert!(Region::new(0, 0, 8, 6).fits(8, 6));
+        assert!(Region::new(2, 1, 3, 4).fits(8, 6));
+        assert!(!Region::new(2, 1, 7, 4).fits(8, 6));
+        assert!(!Region::new(2, 1, 0, 4).fits(8, 6));
+    }
+
+    #[test]
+    fn region_of_overflowing_size_does_not_fit() {
+        assert!(!Region::new(1, 0, usize::MAX, 6).fits(8, 6));
+        assert!(!Region::new(0, usize::MAX, 8, 1).fits(8, 6));
+        assert!(!Region::new(usize::MAX, usize::MAX, usize::MAX, usize::MAX).fits(usize::MAX, usize::MAX));
+    }
+}
@@ -0,0 +1,54 @@
+//! Ray tracing renderer: describe a `Scene` of objects, materials, lights and media,
+//! then render it into an `Image` with a `RenderEngine`.
+
+pub mod vector;
+pub mod camera;
+pub mod color;
+pub mod point;
+pub mod image;
+pub mod utils;
+pub mod object;
+pub mod ray;
+pub mod scene;
+pub mod engine;
+pub mod light;
+pub mod material;
+pub mod hitrecord;
+pub mod transform;
+pub mod volume;
+pub mod voxel;
+pub mod animation;
+pub mod sampler;
+pub mod filter;
+pub mod film;
+pub mod checkpoint;
+pub mod aov;
+pub mod denoise;
+pub mod builder;
+pub mod error;
+pub mod stats;
+pub mod progress;
+#[cfg(feature = "preview")]
+pub mod preview;
+
+pub use vector::{Vector, Matrix3, Matrix4, Quaternion};
+pub use color::Color;
+pub use point::Point;
+pub use camera::Camera;
+pub use image::{Image, Region};
+pub use ray::Ray;
+pub use scene::{Scene, SceneAdd};
+pub use engine::{RenderEngine, Progressive};
+pub use light::Light;
+pub use object::Object;
+pub use material::Material;
+pub use hitrecord::HitRecord;
+pub use transform::Transform;
+pub use volume::Volume;
+pub use sampler::SamplerKind;
+pub use filter::Filter;
+pub use film::Film;
+pub use builder::{SceneBuilder, MaterialLibrary};
+pub use error::{Error, Result};
+pub use stats::RenderStats;
+pub use progress::{Progress, ProgressObserver, CancellationToken};
@@ -1,30 +1,26 @@
-#![allow(dead_code, unused_imports, unused_variables)]
-
-extern crate rand;
-
-mod vector;
-mod color;
-mod point;
-mod image;
-mod utils;
-mod object;
-mod ray;
-mod scene;
-mod engine;
-mod light;
-mod material;
-mod hitrecord;
-
-use vector::Vector;
-use color::Color;
-use point::Point;
-use scene::Scene;
-use object::{Object, Sphere, Plane, Parallelepiped};
-use engine::RenderEngine;
-use light::Light;
-use material::{Material, Metal, Checkered, Dielectric};
-use hitrecord::HitRecord;
-use std::rc::Rc;
+use ray_tracer::{
+    utils,
+    Vector,
+    Color,
+    Point,
+    Scene,
+    Camera,
+    RenderEngine,
+    Progressive,
+    Progress,
+    ProgressObserver,
+    CancellationToken,
+    Region,
+    Result,
+    material::{Metal, Checkered, Dielectric},
+    animation::{Track, Easing},
+    aov::Aov,
+    denoise::{Denoiser, Guides}
+};
+#[cfg(feature = "preview")]
+use ray_tracer::preview::Preview;
+use std::ops::Range;
+use std::time::{Duration, Instant};
 
 
 // 4K
@@ -35,62 +31,283 @@ use std::rc::Rc;
 const WIDTH: usize = 1920;
 const HEIGHT: usize = 1080;
 
-const ASPECT_RATIO: f64 = WIDTH as f64 / HEIGHT as f64;
+const FPS: f64 = 24.0;
+
+/// Limit of rays per pixel when sampling adaptively.
+const MAX_RAYS_PER_PIXEL: usize = 64;
+
+/// Time between the snapshots of progressive rendering.
+const SNAPSHOT_INTERVAL: Duration = Duration::from_secs(10);
+
+
+/// Command line options.
+struct Options {
+    /// Render these frames of the animation instead of a still image.
+    frames: Option<Range<usize>>,
+    /// Pattern of the numbered frame files, see `animation::frame_path`.
+    output: String,
+    /// Error threshold of adaptive sampling, see `RenderEngine::set_adaptive`.
+    adaptive: Option<f64>,
+    /// Where to save the heatmap of the samples taken in each pixel.
+    heatmap: Option<String>,
+    /// Render progressively, saving snapshots to this path.
+    progressive: Option<String>,
+    /// Stop progressive rendering after this time, or render a still image for this long.
+    budget: Option<Duration>,
+    /// Continue the progressive render from its checkpoint.
+    resume: bool,
+    /// Save the AOVs of the render to images starting with this prefix.
+    aovs: Option<String>,
+    /// Denoise the image, guided by the AOVs.
+    denoise: bool,
+    /// Render only this region of the image.
+    crop: Option<Region>,
+    /// Keep the full size of the image when cropping, leaving the rest black.
+    crop_full: bool,
+    /// Print the statistics of the render.
+    stats: bool,
+    /// Save the statistics of the render as JSON to this path.
+    stats_json: Option<String>,
+    /// Pick the view in a preview window before rendering.
+    preview: bool
+}
+
+fn parse_args() -> Options {
+    let usage = "Usage: ray_tracer [--frames START[-END]] [--output frame_####.ppm] [--adaptive THRESHOLD] [--heatmap PATH] [--aovs PREFIX] [--denoise] [--budget SECONDS] [--progressive PATH [--resume]] [--crop X,Y,WIDTH,HEIGHT [--crop-full]] [--stats] [--stats-json PATH] [--preview]";
+    let mut options = Options {
+        frames: None,
+        output: String::from("frame_####.ppm"),
+        adaptive: None,
+        heatmap: None,
+        progressive: None,
+        budget: None,
+        resume: false,
+        aovs: None,
+        denoise: false,
+        crop: None,
+        crop_full: false,
+        stats: false,
+        stats_json: None,
+        preview: false
+    };
+
+    let mut args = std::env::args().skip(1);
+    while let Some(arg) = args.next() {
+        match arg.as_str() {
+            "--resume" => { options.resume = true; continue; },
+            "--denoise" => { options.denoise = true; continue; },
+            "--crop-full" => { options.crop_full = true; continue; },
+            "--stats" => { options.stats = true; continue; },
+            "--preview" => { options.preview = true; continue; },
+            _ => {}
+        }
+        let value = args.next();
+        match (arg.as_str(), value) {
+            ("--frames", Some(range)) => {
+                let bounds: Vec<Option<usize>> = range.splitn(2, '-').map(|n| n.parse().ok()).collect();
+                options.frames = match bounds[..] {
+                    [Some(start), Some(end)] if start <= end => Some(start..end + 1),
+                    [Some(frame)] => Some(frame..frame + 1),
+                    _ => {
+                        eprintln!("Invalid frame range: {}\n{}", range, usage);
+                        std::process::exit(2);
+                    }
+                };
+            },
+            ("--output", Some(path)) => options.output = path,
+            ("--adaptive", Some(threshold)) => match threshold.parse() {
+                Ok(threshold) => options.adaptive = Some(threshold),
+                Err(_) => {
+                    eprintln!("Invalid threshold: {}\n{}", threshold, usage);
+                    std::process::exit(2);
+                }
+            },
+            ("--heatmap", Some(path)) => options.heatmap = Some(path),
+            ("--aovs", Some(prefix)) => options.aovs = Some(prefix),
+            ("--progressive", Some(path)) => options.progressive = Some(path),
+            ("--crop", Some(region)) => {
+                let bounds: Vec<Option<usize>> = region.split(',').map(|n| n.parse().ok()).collect();
+                options.crop = match bounds[..] {
+                    [Some(x), Some(y), Some(width), Some(height)] => Some(Region::new(x, y, width, height)),
+                    _ => {
+                        eprintln!("Invalid crop region: {}\n{}", region, usage);
+                        std::process::exit(2);
+                    }
+                };
+            },
+            ("--stats-json", Some(path)) => options.stats_json = Some(path),
+            ("--budget", Some(seconds)) => match seconds.parse() {
+                Ok(seconds) if seconds >= 0.0 => options.budget = Some(Duration::from_secs_f64(seconds)),
+                _ => {
+                    eprintln!("Invalid budget: {}\n{}", seconds, usage);
+                    std::process::exit(2);
+                }
+            },
+            _ => {
+                eprintln!("{}", usage);
+                std::process::exit(2);
+            }
+        }
+    }
+    // Only single pass renders of still images gather the AOVs.
+    if (options.aovs.is_some() || options.denoise) && (options.frames.is_some() || options.budget.is_some() || options.progressive.is_some()) {
+        eprintln!("--aovs and --denoise don't work with --frames, --budget or --progressive\n{}", usage);
+        std::process::exit(2);
+    }
+    options
+}
+
+
+/// Progress bar of the renders, on the standard error.
+struct ProgressBar;
+
+impl ProgressObserver for ProgressBar {
+    fn update(&mut self, progress: &Progress) -> bool {
+        const LENGTH: usize = 30;
+        let filled = (progress.fraction * LENGTH as f64) as usize;
+        let eta = match progress.eta {
+            Some(eta) => format!("{}:{:02}", eta.as_secs() / 60, eta.as_secs() % 60),
+            None => String::from("-:--")
+        };
+        eprint!(
+            "\r> [{}{}] {:3.0} % {} spp, ETA {} ",
+            "#".repeat(filled), " ".repeat(LENGTH - filled), 100.0 * progress.fraction, progress.samples_per_pixel, eta
+        );
+        true
+    }
+
+    fn finish(&mut self, _progress: &Progress) {
+        eprintln!();
+    }
+}
 
 
 fn main() {
-    let camera = Vector::new(0.0, 0.0, 0.0);
-
-    let lights = vec![
-        // Light::new(Point::new(0.0, 4.0, 4.0), Color::from_hex(0xFFFFFF)),
-        // Light::new(Point::new(0.0, 0.0, 0.0), Color::from_hex(0xFFFFFF))
-        Light::new(Point::new(0.0, 3.0, 2.0), Color::from_hex(0xFFFFFF))
-    ];
-
-    let objects: Vec<Rc<dyn Object>> = vec![
-        Rc::new(
-            // Sphere::new(Point::new(-1.2, 0.5, -2.0), 0.5,
-            Sphere::new(Point::new(-1.1, 0.0, -1.8), 0.5,
-                Rc::new(
-                    Metal::new(Color::from_hex(0x314e52), 0.05, 1.0, 0.5, 0.05)
-                )
-            )
-        ),
-
-        Rc::new(
-            Sphere::new(Point::new(0.0, 0.1, -1.0), 0.4,
-            // Parallelepiped::new(Vector::new(0.3535, 0.3535, 0.0), Vector::new(-0.3535, 0.3535, 0.0), Vector::new(0.0, 0.3535, -0.3535), Vector::new(0.0, -0.4, -1.0),
-            // Parallelepiped::new(Vector::new(0.5, 0.0, 0.0), Vector::new(0.0, 0.5, 0.0), Vector::new(0.0, 0.0, -0.5), Vector::new(-0.25, -0.25, -1.0),
-
-                Rc::new(
-                    // Metal::new(Color::from_hex(0xeb5e0b), 0.05, 0.5, 0.1, 0.5)
-                    Dielectric::new(Color::from_hex(0xFFFFFF), 2.42, 0.0, 0.0, 0.0, 0.0)
-                )
-            )
-        ),
-
-        Rc::new(
-            Sphere::new(Point::new(1.1, 0.0, -2.0), 0.5,
-                Rc::new(
-                    Metal::new(Color::from_hex(0xffd384), 0.05, 0.2, 0.2, 0.8)
-                )
-            )
-        ),
-
-        Rc::new(
-            // Plane::new(Vector::new(0.0, -1.0, 0.1), 0.5,
-            Plane::new(Vector::new(0.0, -1.0, 0.0), 0.5,
-                Rc::new(
-                    Checkered::new(Color::from_hex(0xEED6D3), Color::from_hex(0x67595E), 0.25, 0.25, 0.25, 0.0, 1.0, 0.2, 0.2)
-                )
-            )
-        )
-    ];
-
-    let mut scene = Scene::new(camera, objects, lights, WIDTH, HEIGHT);
-
-    // TODO: Add a `Camera` type and pass scene and camera to `RenderEngine::new`.
-    let engine = RenderEngine::new(10, 5);
-    let image = engine.render(&mut scene);
-    image.show();
+    if let Err(error) = run(parse_args()) {
+        eprintln!("ERROR: {}", error);
+        std::process::exit(1);
+    }
+}
+
+fn run(options: Options) -> Result<()> {
+    let mut engine = RenderEngine::new(10, 5);
+    engine.set_observer(ProgressBar);
+    if let Some(region) = options.crop {
+        engine.set_crop(region, options.crop_full);
+    }
+    if let Some(threshold) = options.adaptive {
+        engine.set_adaptive(threshold, MAX_RAYS_PER_PIXEL);
+    }
+
+    if let Some(frames) = options.frames.clone() {
+        engine.render_animation(frames, FPS, build_scene, &options.output, &mut |frame, path| eprintln!("Frame {} -> {}", frame, path))?;
+        return report_stats(&engine, &options);
+    }
+
+    let mut scene = build_scene(0.0)?;
+    if options.preview {
+        preview(&engine, &mut scene)?;
+    }
+    let (film, aovs) = match &options.progressive {
+        Some(path) => {
+            let settings = Progressive {
+                path: path.clone(),
+                interval: SNAPSHOT_INTERVAL,
+                budget: options.budget,
+                checkpoint: Some(format!("{}.checkpoint", path)),
+                resume: options.resume
+            };
+            // Finish the current row and save the image on Ctrl-C.
+            let token = CancellationToken::new();
+            engine.set_cancellation(token.clone());
+            ctrlc::set_handler(move || token.cancel()).expect("Error setting Ctrl-C handler.");
+            (engine.render_progressive(&scene, &settings)?, None)
+        },
+        None => match options.budget {
+            Some(budget) => {
+                let start_time = Instant::now();
+                let film = engine.render_timed(&scene, budget)?;
+                eprintln!("Rendered {:.2} spp in {:.1} s", film.samples_per_pixel(), start_time.elapsed().as_secs_f64());
+                (film, None)
+            },
+            None if options.aovs.is_some() || options.denoise => {
+                let (film, aovs) = engine.render_with_aovs(&scene)?;
+                (film, Some(aovs))
+            },
+            None => (engine.render_film(&scene)?, None)
+        }
+    };
+
+    if let (Some(prefix), Some(aovs)) = (&options.aovs, &aovs) {
+        aovs.save(prefix)?;
+    }
+    if let Some(path) = &options.heatmap {
+        film.heatmap().save(path)?;
+    }
+    if options.progressive.is_none() {
+        let image = RenderEngine::develop(&film);
+        match (&aovs, options.denoise) {
+            (Some(aovs), true) => {
+                let (albedo, normal, depth) = (aovs.image(Aov::Albedo), aovs.image(Aov::Normal), aovs.image(Aov::Depth));
+                let guides = Guides { albedo: &albedo, normal: &normal, depth: &depth };
+                Denoiser::default().denoise(&image, &guides).show()?;
+            },
+            _ => image.show()?
+        }
+    }
+    report_stats(&engine, &options)
+}
+
+/// Let the user orbit the camera around the spheres in a preview window, then render from there.
+#[cfg(feature = "preview")]
+fn preview(engine: &RenderEngine, scene: &mut Scene) -> Result<()> {
+    let preview = Preview { scale: 2, ..Preview::new(Point::new(0.0, 0.0, -1.5)) };
+    preview.run(engine, scene).map(|_| ())
+}
+
+#[cfg(not(feature = "preview"))]
+fn preview(_engine: &RenderEngine, _scene: &mut Scene) -> Result<()> {
+    Err(ray_tracer::Error::Window(String::from("needs the \"preview\" feature: cargo run --release --features preview -- --preview")))
+}
+
+fn report_stats(engine: &RenderEngine, options: &Options) -> Result<()> {
+    if options.stats {
+        eprintln!("{}", engine.stats());
+    }
+    if let Some(path) = &options.stats_json {
+        std::fs::write(path, engine.stats().to_json() + "\n")?;
+    }
+    Ok(())
+}
+
+/// Build the scene at `time` seconds into the animation, a turntable around the spheres.
+fn build_scene(time: f64) -> Result<Scene> {
+    let target = Point::new(0.0, 0.0, -1.5);
+    let mut angle = Track::new(Easing::Smooth);
+    angle.add_key(0.0, 0.0);
+    angle.add_key(4.0, 360.0);
+
+    let (sin, cos) = utils::degrees_to_radians(angle.at(time)).sin_cos();
+    let position = target + 1.5 * Vector::new(sin, 0.0, cos);
+    let camera = Camera::look_at(position, target, Vector::new(0.0, 1.0, 0.0), 90.0);
+
+    Scene::builder()
+        .size(WIDTH, HEIGHT)
+        .camera(camera)
+        .material("teal", Metal::default().with_color(Color::from_hex(0x314e52)).with_specular(0.5).with_reflection(0.05))
+        .material("diamond", Dielectric::default().with_refractive_index(2.42))
+        .material("gold", Metal::default().with_color(Color::from_hex(0xffd384)).with_diffuse(0.2).with_specular(0.2).with_reflection(0.8))
+        .material("floor", Checkered::default()
+            .with_colors(Color::from_hex(0xEED6D3), Color::from_hex(0x67595E))
+            .with_size(0.25, 0.25)
+            .with_ambience(0.0).with_diffuse(1.0).with_specular(0.2).with_reflection(0.2))
+        // .light(Point::new(0.0, 4.0, 4.0), Color::from_hex(0xFFFFFF))
+        .light(Point::new(0.0, 3.0, 2.0), Color::from_hex(0xFFFFFF))
+        // .sphere(Point::new(-1.2, 0.5, -2.0), 0.5, "teal")
+        .sphere(Point::new(-1.1, 0.0, -1.8), 0.5, "teal")
+        .sphere(Point::new(0.0, 0.1, -1.0), 0.4, "diamond")
+        .sphere(Point::new(1.1, 0.0, -2.0), 0.5, "gold")
+        // .plane(Vector::new(0.0, -1.0, 0.1), 0.5, "floor")
+        .plane(Vector::new(0.0, -1.0, 0.0), 0.5, "floor")
+        .build()
 }
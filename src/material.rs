@@ -1,19 +1,21 @@
 use crate::{
     color::Color,
-    vector::Vector,
     hitrecord::HitRecord,
     ray::Ray,
     scene::Scene,
-    utils
+    sampler::Sampler
 };
 
 
 pub trait Material {
-    fn scatter(&self, hit_record: &HitRecord) -> Ray;
+    /// Direction the ray continues in after hitting the material, drawing any random choice from `sampler`.
+    fn scatter(&self, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Ray;
     // fn color_at(&self, hit_record: &HitRecord) -> Color;
     fn color_at(&self, hit_record: &HitRecord, scene: &Scene) -> Color;
 
     fn get_attenuation(&self, hit_record: &HitRecord) -> Color;
+    /// Color of the surface at the hit, before lighting.
+    fn get_albedo(&self, hit_record: &HitRecord) -> Color;
     fn get_ambience(&self) -> f64;
     fn get_diffuse(&self) -> f64;
     fn get_specular(&self) -> f64;
@@ -22,13 +24,27 @@ pub trait Material {
 
 
 pub struct Dielectric {
+    #[allow(dead_code)]
     color: Color,
     refractive_index: f64,
 
     pub ambience: f64,
     pub diffuse: f64,
     pub specular: f64,
-    pub reflection: f64    
+    pub reflection: f64
+}
+
+impl Default for Dielectric {
+    fn default() -> Self {
+        Self {
+            color: Color::from_hex(0xFFFFFF),
+            refractive_index: 1.5,
+            ambience: 0.0,
+            diffuse: 0.0,
+            specular: 0.0,
+            reflection: 0.0
+        }
+    }
 }
 
 impl Dielectric {
@@ -36,6 +52,30 @@ impl Dielectric {
         Self { color, refractive_index, ambience, diffuse, specular, reflection }
     }
 
+    pub fn with_color(self, color: Color) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_refractive_index(self, refractive_index: f64) -> Self {
+        Self { refractive_index, ..self }
+    }
+
+    pub fn with_ambience(self, ambience: f64) -> Self {
+        Self { ambience, ..self }
+    }
+
+    pub fn with_diffuse(self, diffuse: f64) -> Self {
+        Self { diffuse, ..self }
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub fn with_reflection(self, reflection: f64) -> Self {
+        Self { reflection, ..self }
+    }
+
     pub fn reflectance(cosine: f64, ref_idx: f64) -> f64 {
         // Use Schlick's approximation for reflectance.
         let mut r = (1.0 - ref_idx) / (1.0 + ref_idx);
@@ -45,34 +85,36 @@ impl Dielectric {
 }
 
 impl Material for Dielectric {
-    fn scatter(&self, hit_record: &HitRecord) -> Ray {
+    fn scatter(&self, hit_record: &HitRecord, sampler: &mut dyn Sampler) -> Ray {
         let refraction_ratio = if hit_record.is_out { 1.0 / self.refractive_index } else { self.refractive_index };
         let unit_direction = hit_record.ray.direction.normalize();
-        let refracted = unit_direction.refract(hit_record.normal, refraction_ratio);
-        // return Ray::new(hit_record.position, refracted);
 
         let cos_theta = f64::min(hit_record.normal.dot(-unit_direction), 1.0);
         let sin_theta = (1.0 - cos_theta * cos_theta).sqrt();
 
-        let direction = if refraction_ratio * sin_theta > 1.0 || Self::reflectance(cos_theta, refraction_ratio) > utils::random_double(0.0, 1.0) {
+        let direction = if refraction_ratio * sin_theta > 1.0 || Self::reflectance(cos_theta, refraction_ratio) > sampler.next_1d() {
             unit_direction.reflect(hit_record.normal)
         } else {
             unit_direction.refract(hit_record.normal, refraction_ratio)
         };
 
-        Ray::new(hit_record.position, direction)
+        Ray::with_time(hit_record.position, direction, hit_record.ray.time)
     }
 
-    fn color_at(&self, hit_record: &HitRecord, scene: &Scene) -> Color {
+    fn color_at(&self, _hit_record: &HitRecord, _scene: &Scene) -> Color {
         Color::new(0.0, 0.0, 0.0)
     }
 
-    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+    fn get_attenuation(&self, _hit_record: &HitRecord) -> Color {
         // Color::from_hex(0xFFFFFF)
         // self.color
         Color::new(1.0, 1.0, 1.0)
     }
 
+    fn get_albedo(&self, hit_record: &HitRecord) -> Color {
+        self.get_attenuation(hit_record)
+    }
+
     fn get_ambience(&self) -> f64 { self.ambience }
     fn get_diffuse(&self) -> f64 { self.diffuse }
     fn get_specular(&self) -> f64 { self.specular }
@@ -113,13 +155,34 @@ impl Metal {
             color, ..Self::default()
         }
     }
+
+    pub fn with_color(self, color: Color) -> Self {
+        Self { color, ..self }
+    }
+
+    pub fn with_ambience(self, ambience: f64) -> Self {
+        Self { ambience, ..self }
+    }
+
+    pub fn with_diffuse(self, diffuse: f64) -> Self {
+        Self { diffuse, ..self }
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub fn with_reflection(self, reflection: f64) -> Self {
+        Self { reflection, ..self }
+    }
 }
 
 impl Material for Metal {
-    fn scatter(&self, hit_record: &HitRecord) -> Ray {
-        Ray::new(
+    fn scatter(&self, hit_record: &HitRecord, _sampler: &mut dyn Sampler) -> Ray {
+        Ray::with_time(
             hit_record.position + hit_record.normal * 0.0001,
-            hit_record.ray.direction.reflect(hit_record.normal)
+            hit_record.ray.direction.reflect(hit_record.normal),
+            hit_record.ray.time
         )
     }
 
@@ -129,7 +192,7 @@ impl Material for Metal {
         // let material = hit_record.object.get_material();
 
         let object_color = self.color;
-        let hit_to_camera = scene.camera - hit_position;
+        let hit_to_camera = scene.camera.position - hit_position;
         let specular_k = 50.0;
 
         // Ambience
@@ -153,12 +216,16 @@ impl Material for Metal {
         color
     }
 
-    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+    fn get_attenuation(&self, _hit_record: &HitRecord) -> Color {
         self.get_reflection() * Color::new(1.0, 1.0, 1.0)
         // Color::new(0.0, 0.0, 0.0)
         // Color::new(1.0, 1.0, 1.0)
     }
 
+    fn get_albedo(&self, _hit_record: &HitRecord) -> Color {
+        self.color
+    }
+
     fn get_ambience(&self) -> f64 { self.ambience }
     fn get_diffuse(&self) -> f64 { self.diffuse }
     fn get_specular(&self) -> f64 { self.specular }
@@ -170,6 +237,7 @@ pub struct Checkered {
     color0: Color,
     color1: Color,
     dx: f64,
+    #[allow(dead_code)]
     dy: f64,
     dz: f64,
 
@@ -196,31 +264,50 @@ impl Default for Checkered {
 }
 
 impl Checkered {
+    #[allow(clippy::too_many_arguments)]
     pub fn new(color0: Color, color1: Color, dx: f64, dy: f64, dz: f64, ambience: f64, diffuse: f64, specular: f64, reflection: f64) -> Self {
         Self {
             color0, color1, dx, dy, dz, ambience, diffuse, specular, reflection
         }
     }
+
+    pub fn with_colors(self, color0: Color, color1: Color) -> Self {
+        Self { color0, color1, ..self }
+    }
+
+    /// Size of the squares along x and z.
+    pub fn with_size(self, dx: f64, dz: f64) -> Self {
+        Self { dx, dz, ..self }
+    }
+
+    pub fn with_ambience(self, ambience: f64) -> Self {
+        Self { ambience, ..self }
+    }
+
+    pub fn with_diffuse(self, diffuse: f64) -> Self {
+        Self { diffuse, ..self }
+    }
+
+    pub fn with_specular(self, specular: f64) -> Self {
+        Self { specular, ..self }
+    }
+
+    pub fn with_reflection(self, reflection: f64) -> Self {
+        Self { reflection, ..self }
+    }
 }
 
 impl Material for Checkered {
-    fn scatter(&self, hit_record: &HitRecord) -> Ray {
-        Ray::new(hit_record.position, hit_record.ray.direction.reflect(hit_record.normal))
+    fn scatter(&self, hit_record: &HitRecord, _sampler: &mut dyn Sampler) -> Ray {
+        Ray::with_time(hit_record.position, hit_record.ray.direction.reflect(hit_record.normal), hit_record.ray.time)
     }
 
     fn color_at(&self, hit_record: &HitRecord, scene: &Scene) -> Color {
         let hit_position = hit_record.position;
         let hit_normal = hit_record.normal;
         // let material = hit_record.object.get_material();
-        let x = (hit_record.position.0 / self.dx).ceil() as i32;
-        let z = (hit_record.position.2 / self.dz).ceil() as i32;
-        
-        let object_color = if x & 1 == z & 1 {
-            self.color0
-        } else {
-            self.color1
-        };
-        let hit_to_camera = scene.camera - hit_position;
+        let object_color = self.get_albedo(hit_record);
+        let hit_to_camera = scene.camera.position - hit_position;
         let specular_k = 50.0;
 
         // Ambience
@@ -243,13 +330,45 @@ impl Material for Checkered {
         color
     }
 
-    fn get_attenuation(&self, hit_record: &HitRecord) -> Color {
+    fn get_attenuation(&self, _hit_record: &HitRecord) -> Color {
         // Color::from_hex(0xFFFFFF)
         self.get_reflection() * Color::new(1.0, 1.0, 1.0)
     }
 
+    fn get_albedo(&self, hit_record: &HitRecord) -> Color {
+        let x = (hit_record.position.0 / self.dx).ceil() as i32;
+        let z = (hit_record.position.2 / self.dz).ceil() as i32;
+
+        if x & 1 == z & 1 {
+            self.color0
+        } else {
+            self.color1
+        }
+    }
+
     fn get_ambience(&self) -> f64 { self.ambience }
     fn get_diffuse(&self) -> f64 { self.diffuse }
     fn get_specular(&self) -> f64 { self.specular }
     fn get_reflection(&self) -> f64 { self.reflection }
-}
\ No newline at end of file
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{object::Sphere, point::Point};
+    use std::rc::Rc;
+
+    #[test]
+    fn albedo_is_the_color_of_the_surface() {
+        let gold = Color::from_hex(0xffd384);
+        let metal: Rc<dyn Material> = Rc::new(Metal::default().with_color(gold).with_reflection(0.8));
+        let dielectric: Rc<dyn Material> = Rc::new(Dielectric::default().with_color(gold));
+
+        for (material, expected) in [(metal, gold), (dielectric, Color::new(1.0, 1.0, 1.0))] {
+            let hit_record = HitRecord::new(Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, Rc::clone(&material))));
+            let albedo = material.get_albedo(&hit_record);
+            assert_eq!((albedo.0, albedo.1, albedo.2), (expected.0, expected.1, expected.2));
+        }
+    }
+}
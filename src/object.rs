@@ -1,39 +1,96 @@
+use std::rc::Rc;
+
 use crate::{
     point::Point,
     ray::Ray,
-    color::Color,
-    vector::Vector,
+    vector::{Vector, Matrix3},
     material::Material,
     hitrecord::HitRecord,
-    Rc,
+    transform::{Transform, Motion},
+    stats::{self, ObjectKind},
     utils
 };
 
 
 pub trait Object {
     /// Update `hit_record` with details if there's a hit, else return false.
+    /// Implementations call `stats::count_intersection_test` to appear in the statistics of renders,
+    /// with `ObjectKind::Other` if they're defined outside of the crate.
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool;
 
     /// Return a clone of `material` pointer.
     fn get_material(&self) -> Rc<dyn Material>;
+
+    /// Return every crossing of the surface in `[t_min, t_max)` sorted by distance,
+    /// each one a copy of `hit_record` updated with its details.
+    /// Crossings entering the object have `is_out` set, so for a closed object
+    /// consecutive entry and exit crossings bound the intervals inside of it.
+    fn hit_all(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &HitRecord) -> Vec<HitRecord> {
+        // Gap to step past a crossing without finding it again, relative to its distance
+        // so that it still moves past distant crossings.
+        const DELTA: f64 = 1E-6;
+
+        let mut hit_records = Vec::new();
+        let mut current = hit_record.clone();
+        let mut t = t_min;
+        while self.hit(ray, t, t_max, &mut current) {
+            hit_records.push(current.clone());
+            let next = current.distance + DELTA * current.distance.abs().max(1.0);
+            if next <= t {
+                break;
+            }
+            t = next;
+        }
+        hit_records
+    }
+
+    /// Describe what makes the object degenerate, like a zero radius, if anything.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+
+    /// Name of the kind of object, for statistics.
+    fn kind(&self) -> &'static str {
+        ObjectKind::Other.name()
+    }
 }
 
 
 pub struct Sphere {
     center: Point,
     radius: f64,
-    pub material: Rc<dyn Material>
+    pub material: Rc<dyn Material>,
+
+    /// Center at `time1` when moving, from `center` at `time0`.
+    motion: Option<(Point, f64, f64)>
 }
 
 impl Sphere {
     pub fn new(center: Point, radius: f64, material: Rc<dyn Material>) -> Self {
-        Self { center, radius, material }
+        Self { center, radius, material, motion: None }
+    }
+
+    /// Sphere moving from `center0` at `time0` to `center1` at `time1`, holding still outside that interval.
+    pub fn moving(center0: Point, time0: f64, center1: Point, time1: f64, radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { center: center0, radius, material, motion: Some((center1, time0, time1)) }
+    }
+
+    fn center_at(&self, time: f64) -> Point {
+        match self.motion {
+            Some((center1, time0, time1)) if time1 > time0 => {
+                let t = utils::clamp((time - time0) / (time1 - time0), 0.0, 1.0);
+                self.center + t * (center1 - self.center)
+            },
+            _ => self.center
+        }
     }
 }
 
 impl Object for Sphere {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
-        let sphere_to_ray = ray.origin - self.center;
+        stats::count_intersection_test(ObjectKind::Sphere);
+        let center = self.center_at(ray.time);
+        let sphere_to_ray = ray.origin - center;
         let a = ray.direction.dot(ray.direction);
         let b = ray.direction.dot(sphere_to_ray);
         let c = sphere_to_ray.dot(sphere_to_ray) - (self.radius * self.radius);
@@ -57,7 +114,7 @@ impl Object for Sphere {
         }
 
         // `normal` must always be in opposite direction of incident ray.
-        let normal = (ray.origin + ray.direction * distance - self.center).normalize();
+        let normal = (ray.origin + ray.direction * distance - center).normalize();
         let is_out = normal.dot(ray.direction) < 0.0;
         hit_record.update(
             ray,
@@ -65,12 +122,24 @@ impl Object for Sphere {
             if is_out { normal } else { -normal },
             is_out
         );
+        hit_record.update_uv(
+            (f64::atan2(-normal.2, normal.0) + utils::PI) / (2.0 * utils::PI),
+            f64::acos(-normal.1) / utils::PI
+        );
         true
     }
 
     fn get_material(&self) -> Rc<dyn Material> {
         Rc::clone(&self.material)
     }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Sphere.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        positive("sphere radius", self.radius)
+    }
 }
 
 
@@ -88,6 +157,7 @@ impl Plane {
 
 impl Object for Plane {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Plane);
         // `ray` doesn't intersect if the ray direction is perpendicular to the plane normal.
         let dot = self.unit_normal.dot(ray.direction);
         
@@ -116,121 +186,1030 @@ impl Object for Plane {
     fn get_material(&self) -> Rc<dyn Material> {
         Rc::clone(&self.material)
     }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Plane.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        non_zero("plane normal", self.unit_normal)
+    }
 }
 
 
-pub struct Parallelepiped {
+/// Parallelogram spanned by `x` and `y` from `origin`, facing `x.cross(y)`.
+pub struct Quad {
+    origin: Point,
     x: Vector,
     y: Vector,
-    z: Vector,
-    origin: Vector,
+    unit_normal: Vector,
+    /// `x.cross(y)` divided by its squared length, to project hits onto `x` and `y`.
+    w: Vector,
+    pub material: Rc<dyn Material>
+}
+
+impl Quad {
+    pub fn new(origin: Point, x: Vector, y: Vector, material: Rc<dyn Material>) -> Self {
+        let normal = x.cross(y);
+        Self {
+            origin, x, y,
+            unit_normal: normal.normalize(),
+            w: normal / normal.dot(normal),
+            material
+        }
+    }
+
+    /// Return the distance and the coordinates along `x` and `y` of the hit, if any.
+    fn intersects(&self, ray: &Ray) -> Option<(f64, f64, f64)> {
+        let dot = self.unit_normal.dot(ray.direction);
+        if dot == 0.0 {
+            return None;
+        }
+
+        let distance = self.unit_normal.dot(self.origin - ray.origin) / dot;
+        let planar = ray.origin + ray.direction * distance - self.origin;
+        let alpha = self.w.dot(planar.cross(self.y));
+        let beta = self.w.dot(self.x.cross(planar));
+        if (0.0..=1.0).contains(&alpha) && (0.0..=1.0).contains(&beta) {
+            Some((distance, alpha, beta))
+        } else {
+            None
+        }
+    }
+}
+
+impl Object for Quad {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Quad);
+        match self.intersects(&ray) {
+            Some((distance, alpha, beta)) if distance >= t_min && distance < t_max => {
+                record_hit(hit_record, ray, distance, self.unit_normal, (alpha, beta));
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Quad.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        non_zero("quad normal", self.unit_normal)
+    }
+}
+
+
+pub struct Parallelepiped {
+    /// Faces are built so that each one faces outward for a right-handed `x`, `y` and `z`.
+    faces: Vec<Quad>,
+    /// Edges `x`, `y` and `z`.
+    edges: [Vector; 3],
     material: Rc<dyn Material>
 }
 
 impl Parallelepiped {
     pub fn new(x: Vector, y: Vector, z: Vector, origin: Vector, material: Rc<dyn Material>) -> Self {
-        Self { x, y, z, origin, material }
+        let face = |origin, x, y| Quad::new(origin, x, y, Rc::clone(&material));
+        let faces = vec![
+            face(origin, y, x),
+            face(origin + z, x, y),
+            face(origin, z, y),
+            face(origin + x, y, z),
+            face(origin, x, z),
+            face(origin + y, z, x)
+        ];
+        Self { faces, edges: [x, y, z], material }
     }
 }
 
 impl Object for Parallelepiped {
     fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
-        // `intersections` contains distance of intersection for each face of the solid.
-        let intersections: Vec<Option<f64>> = vec![
-            Parallelogram::new(self.origin, self.x, self.y)
-                .intersects(&ray),
-            Parallelogram::new(self.origin + self.z, self.x, self.y)
-                .intersects(&ray),
-            Parallelogram::new(self.origin, self.y, self.z)
-                .intersects(&ray),
-            Parallelogram::new(self.origin + self.x, self.y, self.z)
-                .intersects(&ray),
-            Parallelogram::new(self.origin, self.x, self.z)
-                .intersects(&ray),
-            Parallelogram::new(self.origin + self.y, self.x, self.z)
-                .intersects(&ray),
-        ];
+        stats::count_intersection_test(ObjectKind::Parallelepiped);
+        // Find the face that has closest hit.
+        let nearest = self.faces.iter()
+            .filter_map(|face| face.intersects(&ray).map(|hit| (face, hit)))
+            .filter(|(_, (distance, _, _))| *distance >= t_min && *distance < t_max)
+            .min_by(|a, b| (a.1).0.total_cmp(&(b.1).0));
+
+        match nearest {
+            Some((face, (distance, alpha, beta))) => {
+                record_hit(hit_record, ray, distance, face.unit_normal, (alpha, beta));
+                true
+            },
+            None => false
+        }
+    }
 
-        let mut min_index: usize = 100;
-        let mut min_distance: f64 = utils::INFINITY;
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
 
-        // Find the face that has closest hit.
-        for (i, distance) in intersections.iter().enumerate() {
-            if matches!(distance, Some(d) if d >= &t_min && d < &t_max && d < &min_distance) {
-                min_distance = distance.unwrap();
-                min_index = i;
-            }
+    fn kind(&self) -> &'static str {
+        ObjectKind::Parallelepiped.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        // The volume spanned by the edges vanishes next to the product of their lengths when they're coplanar.
+        const DELTA: f64 = 1E-9;
+
+        let [x, y, z] = self.edges;
+        let volume = x.dot(y.cross(z)).abs();
+        if volume > DELTA * x.magnitude() * y.magnitude() * z.magnitude() && volume.is_finite() {
+            Ok(())
+        } else {
+            Err(String::from("parallelepiped edges must not be coplanar"))
         }
+    }
+}
 
-        // Return false if no hit.
-        if min_index >= 6 {
+
+/// Box between the corners `min` and `max` with faces aligned to the axes.
+pub struct AxisAlignedBox {
+    min: Point,
+    max: Point,
+    pub material: Rc<dyn Material>
+}
+
+impl AxisAlignedBox {
+    pub fn new(a: Point, b: Point, material: Rc<dyn Material>) -> Self {
+        Self {
+            min: Point::new(a.0.min(b.0), a.1.min(b.1), a.2.min(b.2)),
+            max: Point::new(a.0.max(b.0), a.1.max(b.1), a.2.max(b.2)),
+            material
+        }
+    }
+
+    pub fn min(&self) -> Point {
+        self.min
+    }
+
+    pub fn max(&self) -> Point {
+        self.max
+    }
+
+    /// Return the distances where `ray` enters and leaves the box, with the axis of each face.
+    pub fn slabs(&self, ray: &Ray) -> Option<((f64, usize), (f64, usize))> {
+        slabs(self.min, self.max, ray)
+    }
+}
+
+/// Return the distances where `ray` enters and leaves the box between `min` and `max`,
+/// with the axis of each face. Uses the slab method, so the entry may lie behind the ray origin.
+pub fn slabs(min: Point, max: Point, ray: &Ray) -> Option<((f64, usize), (f64, usize))> {
+    let mut near = (-utils::INFINITY, 0);
+    let mut far = (utils::INFINITY, 0);
+
+    for axis in 0..3 {
+        let inverse = 1.0 / ray.direction[axis];
+        let mut t0 = (min[axis] - ray.origin[axis]) * inverse;
+        let mut t1 = (max[axis] - ray.origin[axis]) * inverse;
+        if inverse < 0.0 {
+            std::mem::swap(&mut t0, &mut t1);
+        }
+        if t0 > near.0 {
+            near = (t0, axis);
+        }
+        if t1 < far.0 {
+            far = (t1, axis);
+        }
+        // A NaN from a ray inside a slab plane never compares as a miss here.
+        if near.0 > far.0 {
+            return None;
+        }
+    }
+    Some((near, far))
+}
+
+impl Object for AxisAlignedBox {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Box);
+        let (near, far) = match self.slabs(&ray) {
+            Some(slabs) => slabs,
+            None => return false
+        };
+
+        // Entering faces oppose the ray along their axis, leaving faces follow it.
+        let (distance, axis, sign) = if near.0 >= t_min && near.0 < t_max {
+            (near.0, near.1, -ray.direction[near.1].signum())
+        } else if far.0 >= t_min && far.0 < t_max {
+            (far.0, far.1, ray.direction[far.1].signum())
+        } else {
             return false;
+        };
+
+        let mut normal = Vector::default();
+        normal[axis] = sign;
+
+        // Map the hit onto the two other axes of the face.
+        let position = ray.origin + ray.direction * distance;
+        let size = self.max - self.min;
+        let (i, j) = ((axis + 1) % 3, (axis + 2) % 3);
+        let uv = ((position[i] - self.min[i]) / size[i], (position[j] - self.min[j]) / size[j]);
+
+        record_hit(hit_record, ray, distance, normal, uv);
+        true
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Box.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        let size = self.max - self.min;
+        positive("box width", size.0)?;
+        positive("box height", size.1)?;
+        positive("box depth", size.2)
+    }
+}
+
+
+/// Fail unless `value`, named `name`, is positive and finite.
+fn positive(name: &str, value: f64) -> Result<(), String> {
+    if value > 0.0 && value.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} must be positive, not {}", name, value))
+    }
+}
+
+/// Fail unless the direction `vector`, named `name`, has a finite non-zero length.
+fn non_zero(name: &str, vector: Vector) -> Result<(), String> {
+    let magnitude = vector.magnitude();
+    if magnitude > 0.0 && magnitude.is_finite() {
+        Ok(())
+    } else {
+        Err(format!("{} must not be zero", name))
+    }
+}
+
+/// Update `hit_record` given the outward `normal` at the hit, flipping it to oppose the ray.
+fn record_hit(hit_record: &mut HitRecord, ray: Ray, distance: f64, normal: Vector, uv: (f64, f64)) {
+    let is_out = normal.dot(ray.direction) <= 0.0;
+    hit_record.update(
+        ray,
+        distance,
+        if is_out { normal } else { -normal },
+        is_out
+    );
+    hit_record.update_uv(uv.0, uv.1);
+}
+
+/// Angle of `(x, y)` around the local axis, mapped to `[0, 1]`.
+fn angle_uv(x: f64, y: f64) -> f64 {
+    (f64::atan2(y, x) + utils::PI) / (2.0 * utils::PI)
+}
+
+/// Hit the disk of `radius` at height `z` of a local frame, returning the distance and UV.
+fn hit_cap(origin: Vector, direction: Vector, z: f64, radius: f64) -> Option<(f64, (f64, f64))> {
+    if direction.2 == 0.0 {
+        return None;
+    }
+    let distance = (z - origin.2) / direction.2;
+    let x = origin.0 + direction.0 * distance;
+    let y = origin.1 + direction.1 * distance;
+    if x * x + y * y > radius * radius {
+        return None;
+    }
+    Some((distance, (0.5 + 0.5 * x / radius, 0.5 + 0.5 * y / radius)))
+}
+
+
+/// Closed cylinder of `radius` along `axis` from `base`, with `axis` spanning its height.
+pub struct Cylinder {
+    base: Point,
+    height: f64,
+    radius: f64,
+    frame: Matrix3,
+    pub material: Rc<dyn Material>
+}
+
+impl Cylinder {
+    pub fn new(base: Point, axis: Vector, radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { base, height: axis.magnitude(), radius, frame: Matrix3::from_axis(axis), material }
+    }
+}
+
+impl Object for Cylinder {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Cylinder);
+        let to_local = self.frame.transpose();
+        let o = to_local * (ray.origin - self.base);
+        let d = to_local * ray.direction;
+
+        // Candidate hits with their outward normal in the local frame.
+        let mut candidates: Vec<(f64, Vector, (f64, f64))> = Vec::with_capacity(4);
+
+        let a = d.0 * d.0 + d.1 * d.1;
+        let b = 2.0 * (o.0 * d.0 + o.1 * d.1);
+        let c = o.0 * o.0 + o.1 * o.1 - self.radius * self.radius;
+        for distance in utils::solve_quadratic(a, b, c) {
+            let p = o + d * distance;
+            if p.2 >= 0.0 && p.2 <= self.height {
+                let normal = Vector::new(p.0, p.1, 0.0) / self.radius;
+                candidates.push((distance, normal, (angle_uv(p.0, p.1), p.2 / self.height)));
+            }
+        }
+        if let Some((distance, uv)) = hit_cap(o, d, 0.0, self.radius) {
+            candidates.push((distance, Vector::new(0.0, 0.0, -1.0), uv));
+        }
+        if let Some((distance, uv)) = hit_cap(o, d, self.height, self.radius) {
+            candidates.push((distance, Vector::new(0.0, 0.0, 1.0), uv));
+        }
+
+        match candidates.into_iter()
+            .filter(|(distance, _, _)| *distance >= t_min && *distance < t_max)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+        {
+            Some((distance, normal, uv)) => {
+                record_hit(hit_record, ray, distance, self.frame * normal, uv);
+                true
+            },
+            None => false
+        }
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Cylinder.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        positive("cylinder height", self.height)?;
+        positive("cylinder radius", self.radius)
+    }
+}
+
+
+/// Closed cone with a base of `radius` at `base` and its apex at `base + axis`.
+pub struct Cone {
+    base: Point,
+    height: f64,
+    radius: f64,
+    frame: Matrix3,
+    pub material: Rc<dyn Material>
+}
+
+impl Cone {
+    pub fn new(base: Point, axis: Vector, radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { base, height: axis.magnitude(), radius, frame: Matrix3::from_axis(axis), material }
+    }
+}
+
+impl Object for Cone {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Cone);
+        let to_local = self.frame.transpose();
+        let o = to_local * (ray.origin - self.base);
+        let d = to_local * ray.direction;
+
+        let mut candidates: Vec<(f64, Vector, (f64, f64))> = Vec::with_capacity(3);
+
+        // The side satisfies x^2 + y^2 = k^2 (height - z)^2.
+        let k = self.radius / self.height;
+        let k2 = k * k;
+        let h = self.height - o.2;
+        let a = d.0 * d.0 + d.1 * d.1 - k2 * d.2 * d.2;
+        let b = 2.0 * (o.0 * d.0 + o.1 * d.1 + k2 * h * d.2);
+        let c = o.0 * o.0 + o.1 * o.1 - k2 * h * h;
+        for distance in utils::solve_quadratic(a, b, c) {
+            let p = o + d * distance;
+            if p.2 >= 0.0 && p.2 <= self.height {
+                // The normal is undefined at the apex, point it along the axis there.
+                let gradient = Vector::new(p.0, p.1, k2 * (self.height - p.2));
+                let normal = if gradient.magnitude() > 0.0 { gradient.normalize() } else { Vector::new(0.0, 0.0, 1.0) };
+                candidates.push((distance, normal, (angle_uv(p.0, p.1), p.2 / self.height)));
+            }
+        }
+        if let Some((distance, uv)) = hit_cap(o, d, 0.0, self.radius) {
+            candidates.push((distance, Vector::new(0.0, 0.0, -1.0), uv));
+        }
+
+        match candidates.into_iter()
+            .filter(|(distance, _, _)| *distance >= t_min && *distance < t_max)
+            .min_by(|a, b| a.0.total_cmp(&b.0))
+        {
+            Some((distance, normal, uv)) => {
+                record_hit(hit_record, ray, distance, self.frame * normal, uv);
+                true
+            },
+            None => false
         }
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Cone.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        positive("cone height", self.height)?;
+        positive("cone radius", self.radius)
+    }
+}
+
 
-        // Outward normal is different for each face.
-        let normal = match min_index {
-            0 => self.y.cross(self.x),
-            1 => self.x.cross(self.y),
-            2 => self.z.cross(self.y),
-            3 => self.y.cross(self.z),
-            4 => self.x.cross(self.z),
-            5 => self.z.cross(self.x),
+/// Flat disk of `radius` around `center`, facing `normal`.
+pub struct Disk {
+    center: Point,
+    radius: f64,
+    frame: Matrix3,
+    pub material: Rc<dyn Material>
+}
+
+impl Disk {
+    pub fn new(center: Point, normal: Vector, radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { center, radius, frame: Matrix3::from_axis(normal), material }
+    }
+}
+
+impl Object for Disk {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Disk);
+        let to_local = self.frame.transpose();
+        let o = to_local * (ray.origin - self.center);
+        let d = to_local * ray.direction;
+
+        match hit_cap(o, d, 0.0, self.radius) {
+            Some((distance, uv)) if distance >= t_min && distance < t_max => {
+                record_hit(hit_record, ray, distance, self.frame.column(2), uv);
+                true
+            },
+            _ => false
+        }
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Disk.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        non_zero("disk normal", self.frame.column(2))?;
+        positive("disk radius", self.radius)
+    }
+}
+
+
+/// Flat ring between `inner_radius` and `outer_radius` around `center`, facing `normal`.
+pub struct Annulus {
+    center: Point,
+    inner_radius: f64,
+    outer_radius: f64,
+    frame: Matrix3,
+    pub material: Rc<dyn Material>
+}
+
+impl Annulus {
+    pub fn new(center: Point, normal: Vector, inner_radius: f64, outer_radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { center, inner_radius, outer_radius, frame: Matrix3::from_axis(normal), material }
+    }
+}
+
+impl Object for Annulus {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Annulus);
+        let to_local = self.frame.transpose();
+        let o = to_local * (ray.origin - self.center);
+        let d = to_local * ray.direction;
+
+        let distance = match hit_cap(o, d, 0.0, self.outer_radius) {
+            Some((distance, _)) if distance >= t_min && distance < t_max => distance,
             _ => return false
-        }.normalize();
+        };
+
+        let p = o + d * distance;
+        let r = (p.0 * p.0 + p.1 * p.1).sqrt();
+        if r < self.inner_radius {
+            return false;
+        }
 
-        let is_out = normal.dot(ray.direction) <= 0.0;
+        // `u` goes around the ring and `v` across it.
+        let v = (r - self.inner_radius) / (self.outer_radius - self.inner_radius);
+        record_hit(hit_record, ray, distance, self.frame.column(2), (angle_uv(p.0, p.1), v));
+        true
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Annulus.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        non_zero("annulus normal", self.frame.column(2))?;
+        positive("annulus outer radius", self.outer_radius)?;
+        if self.inner_radius < 0.0 || self.inner_radius >= self.outer_radius {
+            return Err(format!("annulus inner radius {} must lie in [0, {})", self.inner_radius, self.outer_radius));
+        }
+        Ok(())
+    }
+}
+
+
+/// Torus around `center` in the plane perpendicular to `axis`.
+/// `major_radius` is the distance from the center to the tube and `minor_radius` is the tube radius.
+pub struct Torus {
+    center: Point,
+    major_radius: f64,
+    minor_radius: f64,
+    frame: Matrix3,
+    pub material: Rc<dyn Material>
+}
+
+impl Torus {
+    pub fn new(center: Point, axis: Vector, major_radius: f64, minor_radius: f64, material: Rc<dyn Material>) -> Self {
+        Self { center, major_radius, minor_radius, frame: Matrix3::from_axis(axis), material }
+    }
+}
+
+impl Object for Torus {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Torus);
+        let to_local = self.frame.transpose();
+        let mut o = to_local * (ray.origin - self.center);
+        let d = to_local * ray.direction;
+
+        // Start from the bounding sphere, the quartic is badly conditioned for distant origins.
+        let bound = self.major_radius + self.minor_radius;
+        let b = o.dot(d);
+        let c = o.dot(o) - bound * bound;
+        if c > 0.0 && (b > 0.0 || b * b < c) {
+            return false;
+        }
+        let offset = if c > 0.0 { -b - (b * b - c).sqrt() } else { 0.0 };
+        o += d * offset;
+
+        // (|p|^2 + R^2 - r^2)^2 = 4 R^2 (x^2 + y^2) with p = o + t d and |d| = 1.
+        let r2 = self.major_radius * self.major_radius;
+        let e = o.dot(o) + r2 - self.minor_radius * self.minor_radius;
+        let f = o.dot(d);
+        let roots = utils::solve_quartic(
+            4.0 * f,
+            4.0 * f * f + 2.0 * e - 4.0 * r2 * (d.0 * d.0 + d.1 * d.1),
+            4.0 * f * e - 8.0 * r2 * (o.0 * d.0 + o.1 * d.1),
+            e * e - 4.0 * r2 * (o.0 * o.0 + o.1 * o.1)
+        );
+
+        let distance = match roots.into_iter()
+            .map(|t| t + offset)
+            .find(|distance| *distance >= t_min && *distance < t_max)
+        {
+            Some(distance) => distance,
+            None => return false
+        };
+
+        // The normal points away from the nearest point on the center circle of the tube.
+        let p = to_local * (ray.origin + ray.direction * distance - self.center);
+        let ring = (p.0 * p.0 + p.1 * p.1).sqrt();
+        let normal = Vector::new(p.0 * (1.0 - self.major_radius / ring), p.1 * (1.0 - self.major_radius / ring), p.2)
+            .normalize();
+        let v = (f64::atan2(p.2, ring - self.major_radius) + utils::PI) / (2.0 * utils::PI);
+        record_hit(hit_record, ray, distance, self.frame * normal, (angle_uv(p.0, p.1), v));
+        true
+    }
+
+    fn get_material(&self) -> Rc<dyn Material> {
+        Rc::clone(&self.material)
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Torus.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        non_zero("torus axis", self.frame.column(2))?;
+        positive("torus major radius", self.major_radius)?;
+        positive("torus minor radius", self.minor_radius)
+    }
+}
+
+
+/// Instance places a shared object in the scene through an affine transform.
+/// The same object can be instanced many times without being copied.
+pub struct Instance {
+    object: Rc<dyn Object>,
+    transform: Transform,
+    /// Replaces `transform` for objects moving over time.
+    motion: Option<Motion>
+}
+
+impl Instance {
+    pub fn new(object: Rc<dyn Object>, transform: Transform) -> Self {
+        Self { object, transform, motion: None }
+    }
+
+    pub fn moving(object: Rc<dyn Object>, motion: Motion) -> Self {
+        Self { object, transform: Transform::identity(), motion: Some(motion) }
+    }
+}
+
+impl Object for Instance {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        stats::count_intersection_test(ObjectKind::Instance);
+        let transform = match self.motion {
+            Some(motion) => motion.at(ray.time),
+            None => self.transform
+        };
+
+        // Object space distances are world space distances scaled by `scale`.
+        let (local_ray, scale) = transform.inverse_ray(ray);
+        if !self.object.hit(local_ray, t_min * scale, t_max * scale, hit_record) {
+            return false;
+        }
+
+        let normal = transform.apply_normal(hit_record.normal).normalize();
         hit_record.update(
             ray,
-            intersections[min_index].unwrap(),
-            if is_out { normal } else { -normal },
-            is_out
+            hit_record.distance / scale,
+            normal,
+            hit_record.is_out
         );
         true
     }
 
     fn get_material(&self) -> Rc<dyn Material> {
-        Rc::clone(&self.material)
+        self.object.get_material()
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Instance.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        match self.motion {
+            Some(motion) => motion.validate()?,
+            None => self.transform.validate()?
+        }
+        self.object.validate()
     }
 }
 
 
-struct Parallelogram {
-    origin: Vector,
-    x: Vector,
-    y: Vector
+/// Boolean operation combining the solids of a `Csg` node.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum Operation {
+    Union,
+    Intersection,
+    Difference
 }
 
-impl Parallelogram {
-    pub fn new(origin: Vector, x: Vector, y: Vector) -> Self {
-        Self { origin, x, y }
+impl Operation {
+    fn is_inside(&self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            Operation::Union => in_left || in_right,
+            Operation::Intersection => in_left && in_right,
+            Operation::Difference => in_left && !in_right
+        }
     }
+}
 
-    pub fn intersects(&self, ray: &Ray) -> Option<f64> {
-        let x_position = self.origin + self.x;
-        let y_position = self.origin + self.y;
 
-        let cross = (x_position - self.origin).cross(y_position - self.origin);
-        let dot = cross.dot(ray.direction);
-        if dot == 0.0 {
-            return None;
+/// Csg combines two closed objects using constructive solid geometry.
+/// Hits are records of the `Csg` itself, carrying the material of the child object whose surface was crossed.
+pub struct Csg {
+    operation: Operation,
+    left: Rc<dyn Object>,
+    right: Rc<dyn Object>
+}
+
+impl Csg {
+    pub fn new(operation: Operation, left: Rc<dyn Object>, right: Rc<dyn Object>) -> Self {
+        Self { operation, left, right }
+    }
+
+    pub fn union(left: Rc<dyn Object>, right: Rc<dyn Object>) -> Self {
+        Self::new(Operation::Union, left, right)
+    }
+
+    pub fn intersection(left: Rc<dyn Object>, right: Rc<dyn Object>) -> Self {
+        Self::new(Operation::Intersection, left, right)
+    }
+
+    /// Subtract `right` from `left`.
+    pub fn difference(left: Rc<dyn Object>, right: Rc<dyn Object>) -> Self {
+        Self::new(Operation::Difference, left, right)
+    }
+}
+
+impl Object for Csg {
+    fn hit(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &mut HitRecord) -> bool {
+        match self.hit_all(ray, t_min, t_max, hit_record).into_iter().next() {
+            Some(nearest) => {
+                *hit_record = nearest;
+                true
+            },
+            None => false
         }
+    }
 
-        let distance = (cross.dot(self.origin) - cross.dot(ray.origin)) / dot;
-        if distance < 0.0 || !self.is_inside(ray.origin + ray.direction * distance) {
-            None
+    /// Material of `left`, hits carry the material of the child they crossed.
+    fn get_material(&self) -> Rc<dyn Material> {
+        self.left.get_material()
+    }
+
+    fn kind(&self) -> &'static str {
+        ObjectKind::Csg.name()
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.left.validate()?;
+        self.right.validate()
+    }
+
+    fn hit_all(&self, ray: Ray, t_min: f64, t_max: f64, hit_record: &HitRecord) -> Vec<HitRecord> {
+        stats::count_intersection_test(ObjectKind::Csg);
+        // Children are traced to infinity, since whether the ray starts inside a child
+        // is only known from its first crossing being an exit.
+        let left = self.left.hit_all(ray, t_min, utils::INFINITY, &HitRecord::new(Rc::clone(&self.left)));
+        let right = self.right.hit_all(ray, t_min, utils::INFINITY, &HitRecord::new(Rc::clone(&self.right)));
+
+        let mut in_left = matches!(left.first(), Some(h) if !h.is_out);
+        let mut in_right = matches!(right.first(), Some(h) if !h.is_out);
+        let mut inside = self.operation.is_inside(in_left, in_right);
+
+        // Merge both sorted lists, keeping the crossings that change the combined state.
+        let mut hit_records = Vec::new();
+        let (mut i, mut j) = (0, 0);
+        while i < left.len() || j < right.len() {
+            let crossing = if j >= right.len() || (i < left.len() && left[i].distance <= right[j].distance) {
+                in_left = left[i].is_out;
+                i += 1;
+                &left[i - 1]
+            } else {
+                in_right = right[j].is_out;
+                j += 1;
+                &right[j - 1]
+            };
+
+            if crossing.distance >= t_max {
+                break;
+            }
+
+            let is_inside = self.operation.is_inside(in_left, in_right);
+            if is_inside != inside {
+                inside = is_inside;
+                // `normal` already opposes the ray, only the side of the combined solid changes.
+                let mut record = crossing.clone();
+                record.object = Rc::clone(&hit_record.object);
+                record.material = Some(crossing.material());
+                record.is_out = is_inside;
+                hit_records.push(record);
+            }
+        }
+        hit_records
+    }
+}
+
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Dielectric, transform::Pose, vector::Quaternion};
+
+    const EPSILON: f64 = 1E-9;
+
+    fn material() -> Rc<dyn Material> {
+        Rc::new(Dielectric::default())
+    }
+
+    /// Distance, side and whether the material is the one of the right child, of each
+    /// crossing of the x axis from `x` through unit spheres at 0 and 1 combined by `operation`.
+    fn crossings(operation: Operation, x: f64) -> Vec<(f64, bool, bool)> {
+        let right_material = material();
+        let left: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material()));
+        let right: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(1.0, 0.0, 0.0), 1.0, Rc::clone(&right_material)));
+        let csg: Rc<dyn Object> = Rc::new(Csg::new(operation, left, right));
+
+        let ray = Ray::new(Point::new(x, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let hit_records = csg.hit_all(ray, 0.0, utils::INFINITY, &HitRecord::new(Rc::clone(&csg)));
+        for hit_record in hit_records.iter() {
+            assert!(Rc::ptr_eq(&hit_record.object, &csg));
+        }
+        hit_records.iter()
+            .map(|hit_record| (hit_record.distance, hit_record.is_out, Rc::ptr_eq(&hit_record.material(), &right_material)))
+            .collect()
+    }
+
+    fn assert_crossings_eq(a: Vec<(f64, bool, bool)>, b: Vec<(f64, bool, bool)>) {
+        assert_eq!(a.len(), b.len(), "{:?} != {:?}", a, b);
+        for (a, b) in a.iter().zip(b.iter()) {
+            assert!((a.0 - b.0).abs() < EPSILON && (a.1, a.2) == (b.1, b.2), "{:?} != {:?}", a, b);
+        }
+    }
+
+    /// Distance and normal of the nearest hit of `object` by the ray from `origin` along `direction`.
+    fn hit(object: Rc<dyn Object>, origin: Point, direction: Vector) -> Option<(f64, Vector, bool)> {
+        let mut hit_record = HitRecord::new(Rc::clone(&object));
+        let ray = Ray::new(origin, direction);
+        if object.hit(ray, 0.0, utils::INFINITY, &mut hit_record) {
+            Some((hit_record.distance, hit_record.normal, hit_record.is_out))
         } else {
-            Some(distance)
+            None
+        }
+    }
+
+    fn assert_hit_eq(hit: Option<(f64, Vector, bool)>, distance: f64, normal: Vector, is_out: bool) {
+        let (d, n, out) = hit.expect("no hit");
+        assert!((d - distance).abs() < EPSILON, "distance {} != {}", d, distance);
+        assert!((n - normal).magnitude() < EPSILON, "normal {:?} != {:?}", n, normal);
+        assert_eq!(out, is_out);
+    }
+
+    #[test]
+    fn quad_hits_within_its_edges() {
+        let quad: Rc<dyn Object> = Rc::new(Quad::new(Point::new(0.0, 0.0, 0.0), Vector::new(2.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0), material()));
+        assert_hit_eq(hit(Rc::clone(&quad), Point::new(1.0, 0.5, 5.0), Vector::new(0.0, 0.0, -1.0)), 5.0, Vector::new(0.0, 0.0, 1.0), true);
+        // From behind, the normal still opposes the ray.
+        assert_hit_eq(hit(Rc::clone(&quad), Point::new(1.9, 0.9, -3.0), Vector::new(0.0, 0.0, 1.0)), 3.0, Vector::new(0.0, 0.0, -1.0), false);
+        assert!(hit(Rc::clone(&quad), Point::new(2.1, 0.5, 5.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+        assert!(hit(Rc::clone(&quad), Point::new(1.0, -0.1, 5.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+        assert!(hit(quad, Point::new(1.0, 0.5, 5.0), Vector::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn axis_aligned_box_hits_each_face() {
+        let aabb: Rc<dyn Object> = Rc::new(AxisAlignedBox::new(Point::new(1.0, 1.0, 1.0), Point::new(-1.0, -2.0, -1.0), material()));
+        assert_hit_eq(hit(Rc::clone(&aabb), Point::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 4.0, Vector::new(-1.0, 0.0, 0.0), true);
+        assert_hit_eq(hit(Rc::clone(&aabb), Point::new(0.0, -5.0, 0.5), Vector::new(0.0, 1.0, 0.0)), 3.0, Vector::new(0.0, -1.0, 0.0), true);
+        assert_hit_eq(hit(Rc::clone(&aabb), Point::new(0.5, 0.5, 5.0), Vector::new(0.0, 0.0, -1.0)), 4.0, Vector::new(0.0, 0.0, 1.0), true);
+        // From inside, the face left through.
+        assert_hit_eq(hit(Rc::clone(&aabb), Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0)), 1.0, Vector::new(0.0, -1.0, 0.0), false);
+        assert!(hit(Rc::clone(&aabb), Point::new(-5.0, 1.5, 0.0), Vector::new(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(aabb, Point::new(5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn degenerate_boxes_are_invalid() {
+        assert!(AxisAlignedBox::new(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0), material()).validate().is_ok());
+        assert!(AxisAlignedBox::new(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 0.0, 1.0), material()).validate().is_err());
+
+        let (x, y) = (Vector::new(1.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0));
+        let origin = Vector::new(0.0, 0.0, 0.0);
+        assert!(Parallelepiped::new(x, y, Vector::new(0.2, 0.3, 1.0), origin, material()).validate().is_ok());
+        // Pairwise non-parallel edges in the same plane.
+        assert!(Parallelepiped::new(x, y, Vector::new(1.0, 1.0, 0.0), origin, material()).validate().is_err());
+        assert!(Parallelepiped::new(x, x, Vector::new(0.0, 0.0, 1.0), origin, material()).validate().is_err());
+    }
+
+    #[test]
+    fn cylinder_hits_side_and_caps() {
+        let cylinder: Rc<dyn Object> = Rc::new(Cylinder::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 2.0, 0.0), 1.0, material()));
+        assert_hit_eq(hit(Rc::clone(&cylinder), Point::new(-5.0, 1.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 4.0, Vector::new(-1.0, 0.0, 0.0), true);
+        assert_hit_eq(hit(Rc::clone(&cylinder), Point::new(0.0, 5.0, 0.5), Vector::new(0.0, -1.0, 0.0)), 3.0, Vector::new(0.0, 1.0, 0.0), true);
+        assert_hit_eq(hit(Rc::clone(&cylinder), Point::new(0.0, -5.0, 0.0), Vector::new(0.0, 1.0, 0.0)), 5.0, Vector::new(0.0, -1.0, 0.0), true);
+        // From inside, the normal still opposes the ray.
+        assert_hit_eq(hit(Rc::clone(&cylinder), Point::new(0.0, 1.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 1.0, Vector::new(-1.0, 0.0, 0.0), false);
+        assert!(hit(Rc::clone(&cylinder), Point::new(-5.0, 3.0, 0.0), Vector::new(1.0, 0.0, 0.0)).is_none());
+        assert!(hit(cylinder, Point::new(-5.0, 1.0, 1.5), Vector::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn cone_hits_side_base_and_apex() {
+        let cone: Rc<dyn Object> = Rc::new(Cone::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 2.0, 0.0), 1.0, material()));
+        // Halfway up, the radius is 0.5 and the side faces (-1, k) for the slope k = radius / height.
+        let side = Vector::new(-1.0, 0.5, 0.0).normalize();
+        assert_hit_eq(hit(Rc::clone(&cone), Point::new(-5.0, 1.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 4.5, side, true);
+        assert_hit_eq(hit(Rc::clone(&cone), Point::new(0.5, -5.0, 0.0), Vector::new(0.0, 1.0, 0.0)), 5.0, Vector::new(0.0, -1.0, 0.0), true);
+        let (distance, _, _) = hit(Rc::clone(&cone), Point::new(0.0, 5.0, 0.0), Vector::new(0.0, -1.0, 0.0)).expect("no hit");
+        assert!((distance - 3.0).abs() < EPSILON);
+        assert!(hit(cone, Point::new(-5.0, 1.0, 0.6), Vector::new(1.0, 0.0, 0.0)).is_none());
+    }
+
+    #[test]
+    fn disk_and_annulus_hit_within_their_radii() {
+        let disk: Rc<dyn Object> = Rc::new(Disk::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), 1.0, material()));
+        assert_hit_eq(hit(Rc::clone(&disk), Point::new(0.5, 0.0, 5.0), Vector::new(0.0, 0.0, -1.0)), 5.0, Vector::new(0.0, 0.0, 1.0), true);
+        assert_hit_eq(hit(Rc::clone(&disk), Point::new(0.5, 0.0, -5.0), Vector::new(0.0, 0.0, 1.0)), 5.0, Vector::new(0.0, 0.0, -1.0), false);
+        assert!(hit(Rc::clone(&disk), Point::new(1.5, 0.0, 5.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+        assert!(hit(disk, Point::new(0.0, 0.0, 5.0), Vector::new(1.0, 0.0, 0.0)).is_none());
+
+        let annulus: Rc<dyn Object> = Rc::new(Annulus::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), 0.5, 1.0, material()));
+        assert_hit_eq(hit(Rc::clone(&annulus), Point::new(0.0, 0.75, 5.0), Vector::new(0.0, 0.0, -1.0)), 5.0, Vector::new(0.0, 0.0, 1.0), true);
+        assert!(hit(Rc::clone(&annulus), Point::new(0.25, 0.0, 5.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+        assert!(hit(annulus, Point::new(1.25, 0.0, 5.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+    }
+
+    #[test]
+    fn torus_hits_each_side_of_the_tube() {
+        let torus: Rc<dyn Object> = Rc::new(Torus::new(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, 1.0), 2.0, 0.5, material()));
+        assert_hit_eq(hit(Rc::clone(&torus), Point::new(-10.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0)), 7.5, Vector::new(-1.0, 0.0, 0.0), true);
+        assert_hit_eq(hit(Rc::clone(&torus), Point::new(2.0, 0.0, 10.0), Vector::new(0.0, 0.0, -1.0)), 9.5, Vector::new(0.0, 0.0, 1.0), true);
+        // From the hole, the inner side of the tube.
+        assert_hit_eq(hit(Rc::clone(&torus), Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 1.0, 0.0)), 1.5, Vector::new(0.0, -1.0, 0.0), true);
+        assert!(hit(Rc::clone(&torus), Point::new(0.0, 0.0, 10.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+
+        let ray = Ray::new(Point::new(-10.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let distances: Vec<f64> = torus.hit_all(ray, 0.0, utils::INFINITY, &HitRecord::new(Rc::clone(&torus)))
+            .iter().map(|hit_record| hit_record.distance).collect();
+        assert_eq!(distances.len(), 4, "{:?}", distances);
+        for (distance, expected) in distances.iter().zip([7.5, 8.5, 11.5, 12.5]) {
+            assert!((distance - expected).abs() < 1E-6, "{:?}", distances);
         }
     }
 
-    fn is_inside(&self, point: Vector) -> bool {
-        const DELTA: f64 = 1E-5;
-        let volume = self.x.cross(self.y).magnitude();
-        let calculated: f64 = ((point - self.origin).cross(self.x).magnitude()
-            + (point - self.origin).cross(self.y).magnitude()
-            + (point - self.origin - self.x).cross(self.y).magnitude()
-            + (point - self.origin - self.y).cross(self.x).magnitude()
-        ) / 2.0;
-        (calculated - volume).abs() < DELTA
+    #[test]
+    fn hit_all_steps_past_distant_crossings() {
+        // A grazing ray meets the plane 5e10 away, where an absolute step is lost to rounding.
+        let plane: Rc<dyn Object> = Rc::new(Plane::new(Vector::new(0.0, -1.0, 0.0), 0.5, material()));
+        let ray = Ray::new(Point::new(0.0, 0.0, 0.0), Vector::new(1.0, -1E-11, 0.0));
+        let hit_records = plane.hit_all(ray, 0.0, utils::INFINITY, &HitRecord::new(Rc::clone(&plane)));
+        assert_eq!(hit_records.len(), 1);
+        assert!((hit_records[0].distance - 5E10).abs() < 1.0, "distance {}", hit_records[0].distance);
+
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material()));
+        let csg: Rc<dyn Object> = Rc::new(Csg::union(plane, sphere));
+        // The ray starts inside the half-space of the plane, so only leaving it counts.
+        let hit_records = csg.hit_all(ray, 0.0, utils::INFINITY, &HitRecord::new(Rc::clone(&csg)));
+        assert_eq!(hit_records.len(), 1);
+        assert!((hit_records[0].distance - 5E10).abs() < 1.0, "distance {}", hit_records[0].distance);
+    }
+
+    /// Distance to the hit of the ray from `origin` down -z at `time`, if any.
+    fn hit_at(object: &Rc<dyn Object>, origin: Point, time: f64) -> Option<f64> {
+        let mut hit_record = HitRecord::new(Rc::clone(object));
+        let ray = Ray::with_time(origin, Vector::new(0.0, 0.0, -1.0), time);
+        object.hit(ray, 0.0, utils::INFINITY, &mut hit_record).then_some(hit_record.distance)
+    }
+
+    fn assert_distance_eq(distance: Option<f64>, expected: f64) {
+        let distance = distance.expect("no hit");
+        assert!((distance - expected).abs() < EPSILON, "distance {} != {}", distance, expected);
     }
-}
\ No newline at end of file
+
+    #[test]
+    fn moving_sphere_follows_its_center_over_time() {
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::moving(Point::new(0.0, 0.0, -5.0), 1.0, Point::new(2.0, 0.0, -5.0), 3.0, 0.5, material()));
+        assert_distance_eq(hit_at(&sphere, Point::new(0.0, 0.0, 0.0), 1.0), 4.5);
+        assert_distance_eq(hit_at(&sphere, Point::new(1.0, 0.0, 0.0), 2.0), 4.5);
+        assert_distance_eq(hit_at(&sphere, Point::new(2.0, 0.0, 0.0), 3.0), 4.5);
+        assert!(hit_at(&sphere, Point::new(0.0, 0.0, 0.0), 3.0).is_none());
+        // Outside of the interval, the sphere holds still.
+        assert_distance_eq(hit_at(&sphere, Point::new(0.0, 0.0, 0.0), 0.0), 4.5);
+        assert_distance_eq(hit_at(&sphere, Point::new(2.0, 0.0, 0.0), 4.0), 4.5);
+    }
+
+    #[test]
+    fn moving_instance_interpolates_its_poses() {
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material()));
+        let start = Pose::new(Vector::new(0.0, 0.0, -5.0), Quaternion::identity(), Vector::new(1.0, 1.0, 1.0));
+        let end = Pose::new(Vector::new(2.0, 0.0, -5.0), Quaternion::identity(), Vector::new(2.0, 2.0, 2.0));
+        let instance: Rc<dyn Object> = Rc::new(Instance::moving(sphere, Motion::new(start, 0.0, end, 1.0)));
+        assert_distance_eq(hit_at(&instance, Point::new(0.0, 0.0, 0.0), 0.0), 4.0);
+        assert_distance_eq(hit_at(&instance, Point::new(1.0, 0.0, 0.0), 0.5), 3.5);
+        assert_distance_eq(hit_at(&instance, Point::new(2.0, 0.0, 0.0), 1.0), 3.0);
+        assert!(hit_at(&instance, Point::new(2.0, 0.0, 0.0), 0.0).is_none());
+    }
+
+    #[test]
+    fn csg_union_spans_both_children() {
+        assert_crossings_eq(crossings(Operation::Union, -5.0), vec![(4.0, true, false), (7.0, false, true)]);
+        assert_crossings_eq(crossings(Operation::Union, 0.5), vec![(1.5, false, true)]);
+    }
+
+    #[test]
+    fn csg_intersection_spans_the_overlap() {
+        assert_crossings_eq(crossings(Operation::Intersection, -5.0), vec![(5.0, true, true), (6.0, false, false)]);
+        assert_crossings_eq(crossings(Operation::Intersection, 1.5), vec![]);
+    }
+
+    #[test]
+    fn csg_difference_removes_the_right_child() {
+        assert_crossings_eq(crossings(Operation::Difference, -5.0), vec![(4.0, true, false), (5.0, false, true)]);
+        assert_crossings_eq(crossings(Operation::Difference, -0.5), vec![(0.5, false, true)]);
+    }
+
+    #[test]
+    fn csg_hit_keeps_the_object_hit() {
+        let left: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, material()));
+        let right: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(1.0, 0.0, 0.0), 1.0, material()));
+        let csg: Rc<dyn Object> = Rc::new(Csg::intersection(left, Rc::clone(&right)));
+
+        let mut hit_record = HitRecord::new(Rc::clone(&csg));
+        let ray = Ray::new(Point::new(-5.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        assert!(csg.hit(ray, 0.0, utils::INFINITY, &mut hit_record));
+        assert!(Rc::ptr_eq(&hit_record.object, &csg));
+        assert!(Rc::ptr_eq(&hit_record.material(), &right.get_material()));
+        assert!((hit_record.distance - 5.0).abs() < EPSILON);
+    }
+}
@@ -0,0 +1,199 @@
+use std::time::{Duration, Instant};
+
+use minifb::{Key, MouseButton, MouseMode, Scale, Window, WindowOptions};
+
+use crate::{
+    camera::Camera,
+    color::Color,
+    engine::RenderEngine,
+    error::{Error, Result},
+    film::Film,
+    image::Region,
+    point::Point,
+    scene::Scene,
+    vector::Vector,
+    utils
+};
+
+
+/// Time between refreshes of the window while rendering.
+const REFRESH_INTERVAL: Duration = Duration::from_millis(50);
+
+
+/// Preview shows a progressive render of a scene in a window as it converges, one sample per
+/// pixel at a time. The camera orbits around `target` while dragging with the left mouse button
+/// or holding the arrow keys, and moves closer or further with W and S or the scroll wheel;
+/// every move restarts the render. Enter or closing the window ends the preview, Escape or the
+/// cancellation token of the engine cancels it.
+pub struct Preview {
+    pub target: Point,
+    /// Render at a fraction of the size of the scene, magnified in the window: 1, 2, 4 or 8.
+    pub scale: usize,
+    /// Degrees the camera turns per pixel dragged.
+    pub sensitivity: f64
+}
+
+impl Preview {
+    pub fn new(target: Point) -> Self {
+        Self { target, scale: 1, sensitivity: 0.5 }
+    }
+
+    /// Preview `scene` rendered by `engine` until the window is closed, leaving the camera of the scene
+    /// where the user moved it, and return the film of the preview. Fail with `Error::Cancelled` on Escape
+    /// or once the cancellation token of `engine` is cancelled.
+    pub fn run(&self, engine: &RenderEngine, scene: &mut Scene) -> Result<Film> {
+        let size = (scene.width, scene.height);
+        scene.width = (size.0 / self.scale).max(2);
+        scene.height = (size.1 / self.scale).max(2);
+        let result = self.show(engine, scene);
+        (scene.width, scene.height) = size;
+        result
+    }
+
+    fn show(&self, engine: &RenderEngine, scene: &mut Scene) -> Result<Film> {
+        engine.start(scene)?;
+        let (width, height) = (scene.width, scene.height);
+        let scale = match self.scale {
+            0 | 1 => Scale::X1,
+            2 | 3 => Scale::X2,
+            4..=7 => Scale::X4,
+            _ => Scale::X8
+        };
+        let mut window = Window::new("Preview", width, height, WindowOptions { scale, ..WindowOptions::default() })
+            .map_err(|error| Error::Window(error.to_string()))?;
+        window.set_target_fps(30);
+
+        let mut orbit = Orbit::new(scene.camera.position, self.target);
+        let mut mouse = window.get_mouse_pos(MouseMode::Pass);
+        let mut buffer = vec![0u32; width * height];
+        let mut film = Film::new(width, height, engine.filter());
+        let mut sample = 0;
+        let mut last_refresh = Instant::now();
+        let mut error = None;
+
+        while window.is_open() && !window.is_key_down(Key::Enter) {
+            if window.is_key_down(Key::Escape) || engine.is_cancelled() {
+                return Err(Error::Cancelled);
+            }
+
+            let mut moved = false;
+            if sample < engine.max_rays_per_pixel() {
+                let pixels = Region::new(0, 0, width, height);
+                let completed = engine.render_samples(scene, &mut film, None, sample..sample + 1, pixels, &mut |_, film| {
+                    if engine.is_cancelled() {
+                        return false;
+                    }
+                    if last_refresh.elapsed() < REFRESH_INTERVAL {
+                        return true;
+                    }
+                    last_refresh = Instant::now();
+                    if let Err(e) = refresh(&mut window, &mut buffer, film) {
+                        error = Some(e);
+                        return false;
+                    }
+                    moved = orbit.control(&window, &mut mouse, self.sensitivity);
+                    !moved && window.is_open()
+                });
+                if let Some(error) = error.take() {
+                    return Err(error);
+                }
+                if completed {
+                    sample += 1;
+                    window.set_title(&format!("Preview - {} spp", sample));
+                    refresh(&mut window, &mut buffer, &film)?;
+                }
+            } else {
+                window.update();
+                moved = orbit.control(&window, &mut mouse, self.sensitivity);
+            }
+
+            if moved {
+                let mut camera = orbit.camera(scene.camera.horizontal_fov());
+                camera.set_shutter(scene.camera.shutter_open, scene.camera.shutter_close);
+                scene.camera = camera;
+                film = Film::new(width, height, engine.filter());
+                sample = 0;
+            }
+        }
+        Ok(film)
+    }
+}
+
+
+/// Position of the camera around its target, in degrees and scene units.
+struct Orbit {
+    target: Point,
+    distance: f64,
+    yaw: f64,
+    pitch: f64
+}
+
+impl Orbit {
+    fn new(position: Point, target: Point) -> Self {
+        let offset = position - target;
+        let distance = offset.magnitude().max(1E-3);
+        Self {
+            target,
+            distance,
+            yaw: f64::atan2(offset.0, offset.2).to_degrees(),
+            pitch: (offset.1 / distance).asin().to_degrees()
+        }
+    }
+
+    fn camera(&self, horizontal_fov: f64) -> Camera {
+        let (yaw, pitch) = (utils::degrees_to_radians(self.yaw), utils::degrees_to_radians(self.pitch));
+        let direction = Vector::new(pitch.cos() * yaw.sin(), pitch.sin(), pitch.cos() * yaw.cos());
+        Camera::look_at(self.target + self.distance * direction, self.target, Vector::new(0.0, 1.0, 0.0), horizontal_fov)
+    }
+
+    /// Move according to the mouse and keys held in `window`, `mouse` being the last position
+    /// of the mouse. Return whether the camera moved.
+    fn control(&mut self, window: &Window, mouse: &mut Option<(f32, f32)>, sensitivity: f64) -> bool {
+        // Degrees turned and factor zoomed per refresh while holding a key.
+        const TURN: f64 = 5.0;
+        const ZOOM: f64 = 1.1;
+
+        let (yaw, pitch, distance) = (self.yaw, self.pitch, self.distance);
+        let position = window.get_mouse_pos(MouseMode::Pass);
+        if let (Some((x0, y0)), Some((x1, y1)), true) = (*mouse, position, window.get_mouse_down(MouseButton::Left)) {
+            self.yaw -= (x1 - x0) as f64 * sensitivity;
+            self.pitch += (y1 - y0) as f64 * sensitivity;
+        }
+        *mouse = position;
+
+        for key in window.get_keys() {
+            match key {
+                Key::Left => self.yaw -= TURN,
+                Key::Right => self.yaw += TURN,
+                Key::Up => self.pitch += TURN,
+                Key::Down => self.pitch -= TURN,
+                Key::W => self.distance /= ZOOM,
+                Key::S => self.distance *= ZOOM,
+                _ => {}
+            }
+        }
+        if let Some((_, scroll)) = window.get_scroll_wheel() {
+            self.distance *= ZOOM.powf(-scroll.signum() as f64);
+        }
+
+        // Keep the camera off the poles, where its up direction is undefined.
+        self.pitch = utils::clamp(self.pitch, -89.0, 89.0);
+        (self.yaw, self.pitch, self.distance) != (yaw, pitch, distance)
+    }
+}
+
+
+/// Show the gamma-corrected `film` in `window`.
+fn refresh(window: &mut Window, buffer: &mut [u32], film: &Film) -> Result<()> {
+    for y in 0..film.height() {
+        for x in 0..film.width() {
+            buffer[y * film.width() + x] = to_rgb(film.get_pixel(x, y).powf(0.5));
+        }
+    }
+    window.update_with_buffer(buffer, film.width(), film.height()).map_err(|error| Error::Window(error.to_string()))
+}
+
+fn to_rgb(color: Color) -> u32 {
+    let byte = |value: f64| (256.0 * utils::clamp(value, 0.0, 0.999)) as u32;
+    (byte(color.0) << 16) | (byte(color.1) << 8) | byte(color.2)
+}
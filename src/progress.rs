@@ -0,0 +1,93 @@
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::time::{Duration, Instant};
+
+
+/// Progress of a render, reported to the `ProgressObserver` of the engine after each row.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Progress {
+    /// Rows completed in the pass in progress, out of `rows`.
+    pub rows_done: usize,
+    pub rows: usize,
+    /// Samples per pixel the pass in progress brings the image to.
+    pub samples_per_pixel: usize,
+    /// Fraction of the whole render done, in `[0, 1]`.
+    pub fraction: f64,
+    pub elapsed: Duration,
+    /// Estimated time left, once there's anything to estimate it from.
+    pub eta: Option<Duration>
+}
+
+
+/// ProgressObserver follows a render, to show its progress or cancel it.
+/// Closures taking a `&Progress` and returning whether to go on are observers.
+pub trait ProgressObserver {
+    /// Called after each row rendered, return false to cancel the render.
+    fn update(&mut self, progress: &Progress) -> bool;
+
+    /// Called once the render is over, whether it finished or not.
+    fn finish(&mut self, _progress: &Progress) {}
+}
+
+impl<F: FnMut(&Progress) -> bool> ProgressObserver for F {
+    fn update(&mut self, progress: &Progress) -> bool {
+        self(progress)
+    }
+}
+
+
+/// CancellationToken cancels the renders of the engine it's given to, from any thread.
+/// Renders check it between rows; clones share the same state.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+
+    /// Let renders run again.
+    pub fn reset(&self) {
+        self.0.store(false, Ordering::Relaxed);
+    }
+}
+
+
+/// Tracker times a render to estimate when it will end.
+pub(crate) struct Tracker {
+    start: Instant,
+    /// Fraction already done when the render started, like when resuming it.
+    start_fraction: f64,
+    last: Progress
+}
+
+impl Tracker {
+    pub fn new(start_fraction: f64) -> Self {
+        Self { start: Instant::now(), start_fraction, last: Progress { fraction: start_fraction, ..Progress::default() } }
+    }
+
+    pub fn update(&mut self, rows_done: usize, rows: usize, samples_per_pixel: usize, fraction: f64) -> Progress {
+        let elapsed = self.start.elapsed();
+        let fraction = fraction.clamp(0.0, 1.0);
+        let done = fraction - self.start_fraction;
+        let eta = if done > 0.0 {
+            Some(elapsed.mul_f64((1.0 - fraction) / done))
+        } else {
+            None
+        };
+        self.last = Progress { rows_done, rows, samples_per_pixel, fraction, elapsed, eta };
+        self.last
+    }
+
+    pub fn last(&self) -> Progress {
+        Progress { elapsed: self.start.elapsed(), ..self.last }
+    }
+}
@@ -7,23 +7,31 @@ use crate::{
 #[derive(Debug, Copy, Clone)]
 pub struct Ray {
     pub origin: Point,
-    pub direction: Vector
+    pub direction: Vector,
+    /// Moment at which the ray travels, for moving objects.
+    pub time: f64
 }
 
 impl Default for Ray {
     fn default() -> Self {
         Self {
             origin: Point::new(0.0, 0.0, 0.0),
-            direction: Vector::new(0.0, 0.0, 0.0)
+            direction: Vector::new(0.0, 0.0, 0.0),
+            time: 0.0
         }
     }
 }
 
 impl Ray {
     pub fn new(origin: Point, direction: Vector) -> Self {
+        Self::with_time(origin, direction, 0.0)
+    }
+
+    pub fn with_time(origin: Point, direction: Vector, time: f64) -> Self {
         Self {
             origin,
-            direction: direction.normalize()
+            direction: direction.normalize(),
+            time
         }
     }
-}
\ No newline at end of file
+}
@@ -0,0 +1,404 @@
+/// Sampler generates the random numbers of each pixel sample, one dimension at a time.
+/// Dimensions are consumed in the same order for every sample, so well distributed
+/// samplers can spread the values of each dimension evenly over the samples of a pixel.
+/// The numbers only depend on the seed of the sampler, the pixel and the sample index,
+/// so samples can be taken in any order, on any thread, and still give the same image.
+pub trait Sampler {
+    /// Start generating the `index`-th sample of pixel `(x, y)`.
+    fn start_sample(&mut self, x: usize, y: usize, index: usize);
+
+    /// Next dimension of the current sample, in `[0, 1)`.
+    fn next_1d(&mut self) -> f64;
+
+    /// Next two dimensions of the current sample, in `[0, 1)`.
+    fn next_2d(&mut self) -> (f64, f64);
+}
+
+
+/// Kinds of samplers the engine can render with.
+#[derive(Debug, Copy, Clone, PartialEq, Default)]
+pub enum SamplerKind {
+    Random,
+    Stratified,
+    Halton,
+    #[default]
+    Sobol
+}
+
+impl SamplerKind {
+    /// Create a sampler for pixels taking `samples_per_pixel` samples, whose numbers are chosen by `seed`.
+    pub fn create(&self, samples_per_pixel: usize, seed: u64) -> Box<dyn Sampler> {
+        match self {
+            SamplerKind::Random => Box::new(RandomSampler::new(seed)),
+            SamplerKind::Stratified => Box::new(StratifiedSampler::new(samples_per_pixel, seed)),
+            SamplerKind::Halton => Box::new(HaltonSampler::new(seed)),
+            SamplerKind::Sobol => Box::new(SobolSampler::new(seed))
+        }
+    }
+}
+
+
+/// Small pseudo-random number generator (SplitMix64), cheap enough to be reseeded for every sample.
+#[derive(Debug, Copy, Clone)]
+pub struct Rng {
+    state: u64
+}
+
+impl Rng {
+    pub fn new(seed: u64) -> Self {
+        Self { state: seed }
+    }
+
+    pub fn next_u64(&mut self) -> u64 {
+        self.state = self.state.wrapping_add(0x9E3779B97F4A7C15);
+        let mut z = self.state;
+        z = (z ^ (z >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        z = (z ^ (z >> 27)).wrapping_mul(0x94D049BB133111EB);
+        z ^ (z >> 31)
+    }
+
+    /// Uniform number in `[0, 1)`.
+    pub fn next_f64(&mut self) -> f64 {
+        to_unit(self.next_u64())
+    }
+}
+
+
+/// Independent uniform random numbers.
+pub struct RandomSampler {
+    seed: u64,
+    rng: Rng
+}
+
+impl RandomSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, rng: Rng::new(seed) }
+    }
+}
+
+impl Sampler for RandomSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.rng = Rng::new(hash(&[self.seed, x as u64, y as u64, index as u64]));
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        self.rng.next_f64()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next_1d(), self.next_1d())
+    }
+}
+
+
+/// Jittered strata: the samples of a pixel fall in distinct strata of each dimension,
+/// and in distinct cells of a grid for 2D dimensions. Strata are shuffled per pixel
+/// and dimension so that dimensions stay uncorrelated.
+pub struct StratifiedSampler {
+    samples_per_pixel: usize,
+    /// Columns and rows of the 2D grid of strata.
+    grid: (usize, usize),
+    seed: u64,
+    pixel_seed: u64,
+    index: usize,
+    dimension: u64,
+    /// Jitter inside the strata.
+    rng: Rng
+}
+
+impl StratifiedSampler {
+    pub fn new(samples_per_pixel: usize, seed: u64) -> Self {
+        let samples_per_pixel = samples_per_pixel.max(1);
+        let columns = (samples_per_pixel as f64).sqrt() as usize;
+        let rows = samples_per_pixel.div_ceil(columns);
+        Self {
+            samples_per_pixel,
+            grid: (columns, rows),
+            seed,
+            pixel_seed: 0,
+            index: 0,
+            dimension: 0,
+            rng: Rng::new(seed)
+        }
+    }
+
+    /// Stratum of the current sample among `count`, shuffled for the current dimension.
+    fn stratum(&self, count: usize) -> usize {
+        let seed = hash(&[self.pixel_seed, self.dimension]);
+        permute(self.index % count, count, seed)
+    }
+}
+
+impl Sampler for StratifiedSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::new(hash(&[self.pixel_seed, index as u64]));
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let stratum = self.stratum(self.samples_per_pixel);
+        self.dimension += 1;
+        (stratum as f64 + self.rng.next_f64()) / self.samples_per_pixel as f64
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let (columns, rows) = self.grid;
+        let cell = self.stratum(columns * rows);
+        self.dimension += 1;
+        (
+            ((cell % columns) as f64 + self.rng.next_f64()) / columns as f64,
+            ((cell / columns) as f64 + self.rng.next_f64()) / rows as f64
+        )
+    }
+}
+
+
+const PRIMES: [u64; 32] = [
+    2, 3, 5, 7, 11, 13, 17, 19, 23, 29, 31, 37, 41, 43, 47, 53,
+    59, 61, 67, 71, 73, 79, 83, 89, 97, 101, 103, 107, 109, 113, 127, 131
+];
+
+/// Halton sequence using a prime base per dimension, shifted by a random offset per pixel
+/// and dimension (Cranley-Patterson rotation) to decorrelate pixels. Dimensions past
+/// the table of primes fall back to random numbers.
+pub struct HaltonSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: usize,
+    dimension: usize,
+    rng: Rng
+}
+
+impl HaltonSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel_seed: 0, index: 0, dimension: 0, rng: Rng::new(seed) }
+    }
+
+    fn next(&mut self) -> f64 {
+        let dimension = self.dimension;
+        self.dimension += 1;
+        if dimension >= PRIMES.len() {
+            return self.rng.next_f64();
+        }
+
+        let value = radical_inverse(self.index as u64 + 1, PRIMES[dimension]);
+        let offset = to_unit(hash(&[self.pixel_seed, dimension as u64]));
+        (value + offset).fract()
+    }
+}
+
+impl Sampler for HaltonSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index;
+        self.dimension = 0;
+        self.rng = Rng::new(hash(&[self.pixel_seed, index as u64]));
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        self.next()
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        (self.next(), self.next())
+    }
+}
+
+/// Mirror the digits of `index` in `base` around the radix point.
+fn radical_inverse(mut index: u64, base: u64) -> f64 {
+    let inverse_base = 1.0 / base as f64;
+    let mut scale = inverse_base;
+    let mut result = 0.0;
+    while index > 0 {
+        result += (index % base) as f64 * scale;
+        index /= base;
+        scale *= inverse_base;
+    }
+    result
+}
+
+
+/// Owen-scrambled Sobol sequence, after Burley's "Practical Hash-based Owen Scrambling".
+/// Each 1D or 2D request uses the first Sobol dimensions with its own scrambling seed
+/// and shuffled sample order, which keeps every pair of dimensions well stratified.
+pub struct SobolSampler {
+    seed: u64,
+    pixel_seed: u64,
+    index: u32,
+    dimension: u64
+}
+
+impl SobolSampler {
+    pub fn new(seed: u64) -> Self {
+        Self { seed, pixel_seed: 0, index: 0, dimension: 0 }
+    }
+
+    /// Seed for the current dimension, advancing to the next one.
+    fn next_seed(&mut self) -> u64 {
+        let seed = hash(&[self.pixel_seed, self.dimension]);
+        self.dimension += 1;
+        seed
+    }
+}
+
+impl Sampler for SobolSampler {
+    fn start_sample(&mut self, x: usize, y: usize, index: usize) {
+        self.pixel_seed = hash(&[self.seed, x as u64, y as u64]);
+        self.index = index as u32;
+        self.dimension = 0;
+    }
+
+    fn next_1d(&mut self) -> f64 {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        to_unit_u32(nested_uniform_scramble(sobol(index, 0), (seed >> 32) as u32))
+    }
+
+    fn next_2d(&mut self) -> (f64, f64) {
+        let seed = self.next_seed();
+        let index = nested_uniform_scramble(self.index, seed as u32);
+        let seed = hash(&[seed]);
+        (
+            to_unit_u32(nested_uniform_scramble(sobol(index, 0), seed as u32)),
+            to_unit_u32(nested_uniform_scramble(sobol(index, 1), (seed >> 32) as u32))
+        )
+    }
+}
+
+/// Sobol point `index` in one of the first two dimensions, as a 32 bit fraction.
+fn sobol(mut index: u32, dimension: usize) -> u32 {
+    let mut result = 0;
+    // The first dimension is the van der Corput sequence, the second uses the
+    // primitive polynomial x + 1, giving direction numbers v[k] = v[k - 1] ^ (v[k - 1] >> 1).
+    let mut direction: u32 = 1 << 31;
+    while index != 0 {
+        if index & 1 == 1 {
+            result ^= direction;
+        }
+        index >>= 1;
+        direction = if dimension == 0 { direction >> 1 } else { direction ^ (direction >> 1) };
+    }
+    result
+}
+
+/// Random permutation of the bits of `x` that only depends on the more significant bits,
+/// by Laine and Karras, applied to reversed bits to get an Owen scramble.
+fn nested_uniform_scramble(x: u32, seed: u32) -> u32 {
+    let mut x = x.reverse_bits();
+    x = x.wrapping_add(seed);
+    x ^= x.wrapping_mul(0x6c50b47c);
+    x ^= x.wrapping_mul(0xb82f1e52);
+    x ^= x.wrapping_mul(0xc7afe638);
+    x ^= x.wrapping_mul(0x8d22f6e6);
+    x.reverse_bits()
+}
+
+
+/// Mix `values` into a well distributed 64 bit hash, using the SplitMix64 finalizer.
+pub fn hash(values: &[u64]) -> u64 {
+    let mut h: u64 = 0x9E3779B97F4A7C15;
+    for value in values {
+        h ^= value.wrapping_add(0x9E3779B97F4A7C15).wrapping_add(h << 6).wrapping_add(h >> 2);
+        h = (h ^ (h >> 30)).wrapping_mul(0xBF58476D1CE4E5B9);
+        h = (h ^ (h >> 27)).wrapping_mul(0x94D049BB133111EB);
+        h ^= h >> 31;
+    }
+    h
+}
+
+/// Element at `index` of a random permutation of `0..count` chosen by `seed`,
+/// using a hashed Feistel-like cycle walk so that nothing is stored.
+fn permute(index: usize, count: usize, seed: u64) -> usize {
+    if count <= 1 {
+        return 0;
+    }
+    // Cycle-walk a bijection on the next power of two until landing inside `0..count`.
+    let mask = count.next_power_of_two() as u64 - 1;
+    let mut i = index as u64;
+    loop {
+        for round in 0..4 {
+            i = (i ^ (hash(&[seed, round]) & mask)) & mask;
+            i = (i.wrapping_mul(hash(&[seed, round, 1]) | 1)) & mask;
+        }
+        if (i as usize) < count {
+            return i as usize;
+        }
+    }
+}
+
+/// Map the upper 53 bits of `value` to `[0, 1)`.
+fn to_unit(value: u64) -> f64 {
+    (value >> 11) as f64 / (1u64 << 53) as f64
+}
+
+fn to_unit_u32(value: u32) -> f64 {
+    value as f64 / 4294967296.0
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const KINDS: [SamplerKind; 4] = [SamplerKind::Random, SamplerKind::Stratified, SamplerKind::Halton, SamplerKind::Sobol];
+
+    #[test]
+    fn samples_lie_in_unit_interval() {
+        for kind in KINDS {
+            let mut sampler = kind.create(16, 5);
+            for (x, y) in [(0, 0), (3, 7), (1000, 1)] {
+                for index in 0..64 {
+                    sampler.start_sample(x, y, index);
+                    // Past the table of primes of the Halton sampler too.
+                    for _ in 0..20 {
+                        let value = sampler.next_1d();
+                        let (u, v) = sampler.next_2d();
+                        for value in [value, u, v] {
+                            assert!((0.0..1.0).contains(&value), "{:?} gave {}", kind, value);
+                        }
+                    }
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn samples_of_a_pixel_are_stratified() {
+        const SAMPLES: usize = 16;
+        for kind in [SamplerKind::Stratified, SamplerKind::Sobol] {
+            let mut sampler = kind.create(SAMPLES, 9);
+            for dimension in 0..6 {
+                let mut strata = [false; SAMPLES];
+                let mut cells = [false; SAMPLES];
+                for index in 0..SAMPLES {
+                    sampler.start_sample(2, 3, index);
+                    for _ in 0..dimension {
+                        sampler.next_1d();
+                        sampler.next_2d();
+                    }
+                    strata[(sampler.next_1d() * SAMPLES as f64) as usize] = true;
+                    let (u, v) = sampler.next_2d();
+                    cells[(v * 4.0) as usize * 4 + (u * 4.0) as usize] = true;
+                }
+                assert!(strata.iter().all(|s| *s), "{:?} 1D dimension {} isn't stratified", kind, dimension);
+                assert!(cells.iter().all(|c| *c), "{:?} 2D dimension {} isn't stratified", kind, dimension);
+            }
+        }
+    }
+
+    #[test]
+    fn permute_is_a_bijection() {
+        for count in 1..70 {
+            for seed in 0..8 {
+                let mut seen = vec![false; count];
+                for index in 0..count {
+                    let permuted = permute(index, count, seed);
+                    assert!(permuted < count && !seen[permuted], "permute({}, {}, {}) repeats {}", index, count, seed, permuted);
+                    seen[permuted] = true;
+                }
+            }
+        }
+    }
+}
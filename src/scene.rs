@@ -1,31 +1,75 @@
+use std::rc::Rc;
+
 use crate::{
-    vector::Vector,
+    camera::Camera,
     object::Object,
     light::Light,
-    Rc
+    volume::{Volume, Medium},
+    builder::SceneBuilder,
+    error::{Error, Result}
 };
 
 
 /// Scene has all the information needed for the ray-tracing engine.
 pub struct Scene {
-    pub camera: Vector,
+    pub camera: Camera,
     pub objects: Vec<Rc<dyn Object>>,
     pub lights: Vec<Light>,
     pub width: usize,
-    pub height: usize
+    pub height: usize,
+
+    /// Participating media bounded by objects.
+    pub volumes: Vec<Rc<dyn Volume>>,
+    /// Medium filling the whole scene, like atmospheric fog.
+    pub fog: Option<Medium>
 }
 
 
 impl Scene {
-    pub fn new(camera: Vector, objects: Vec<Rc<dyn Object>>, lights: Vec<Light>, width: usize, height: usize) -> Self {
+    pub fn new(camera: Camera, objects: Vec<Rc<dyn Object>>, lights: Vec<Light>, width: usize, height: usize) -> Self {
         Self {
-            camera, objects, lights, width, height
+            camera, objects, lights, width, height,
+            volumes: Vec::new(),
+            fog: None
         }
     }
+
+    /// Check the scene can be rendered, reporting every degenerate object, volume or setting.
+    pub fn validate(&self) -> Result<()> {
+        let mut problems = Vec::new();
+        // The camera spreads the pixels between the first and last column and row.
+        if self.width < 2 || self.height < 2 {
+            problems.push(format!("image must be at least 2x2 pixels, not {}x{}", self.width, self.height));
+        }
+        for (index, object) in self.objects.iter().enumerate() {
+            if let Err(problem) = object.validate() {
+                problems.push(format!("object {}: {}", index, problem));
+            }
+        }
+        for (index, volume) in self.volumes.iter().enumerate() {
+            if let Err(problem) = volume.validate() {
+                problems.push(format!("volume {}: {}", index, problem));
+            }
+        }
+        if let Some(Err(problem)) = self.fog.map(|fog| fog.validate()) {
+            problems.push(format!("fog: {}", problem));
+        }
+
+        if problems.is_empty() {
+            Ok(())
+        } else {
+            Err(Error::InvalidScene(problems))
+        }
+    }
+
+    /// Start building a scene, see `SceneBuilder`.
+    pub fn builder() -> SceneBuilder {
+        SceneBuilder::new()
+    }
 }
 
 pub trait SceneAdd<T> {
-    /// Add an object or light to the scene.
+    /// Add an object, light or volume to the scene.
     fn add(&mut self, node: T);
 }
 
@@ -40,3 +84,9 @@ impl SceneAdd<Light> for Scene {
         self.lights.push(light);
     }
 }
+
+impl SceneAdd<Rc<dyn Volume>> for Scene {
+    fn add(&mut self, volume: Rc<dyn Volume>) {
+        self.volumes.push(volume);
+    }
+}
@@ -0,0 +1,191 @@
+use std::cell::Cell;
+use std::fmt;
+use std::time::Duration;
+
+
+/// Kind of object whose intersection tests are counted, see `count_intersection_test`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum ObjectKind {
+    Sphere,
+    Plane,
+    Quad,
+    Parallelepiped,
+    Box,
+    Cylinder,
+    Cone,
+    Disk,
+    Annulus,
+    Torus,
+    Instance,
+    Csg,
+    /// Any object defined outside of the crate.
+    Other
+}
+
+impl ObjectKind {
+    const ALL: [ObjectKind; 13] = [
+        ObjectKind::Sphere, ObjectKind::Plane, ObjectKind::Quad, ObjectKind::Parallelepiped, ObjectKind::Box,
+        ObjectKind::Cylinder, ObjectKind::Cone, ObjectKind::Disk, ObjectKind::Annulus, ObjectKind::Torus,
+        ObjectKind::Instance, ObjectKind::Csg, ObjectKind::Other
+    ];
+
+    /// Name of the kind in statistics, as returned by `Object::kind`.
+    pub fn name(self) -> &'static str {
+        match self {
+            ObjectKind::Sphere => "sphere",
+            ObjectKind::Plane => "plane",
+            ObjectKind::Quad => "quad",
+            ObjectKind::Parallelepiped => "parallelepiped",
+            ObjectKind::Box => "box",
+            ObjectKind::Cylinder => "cylinder",
+            ObjectKind::Cone => "cone",
+            ObjectKind::Disk => "disk",
+            ObjectKind::Annulus => "annulus",
+            ObjectKind::Torus => "torus",
+            ObjectKind::Instance => "instance",
+            ObjectKind::Csg => "csg",
+            ObjectKind::Other => "object"
+        }
+    }
+}
+
+
+thread_local! {
+    /// Intersection tests made on this thread since they were last taken, indexed by `ObjectKind`.
+    /// Plain cells, since counting happens on every test of every ray.
+    static INTERSECTION_TESTS: [Cell<u64>; ObjectKind::ALL.len()] = const { [const { Cell::new(0) }; ObjectKind::ALL.len()] };
+}
+
+/// Count a ray-object intersection test against an object of kind `kind`.
+#[inline]
+pub fn count_intersection_test(kind: ObjectKind) {
+    INTERSECTION_TESTS.with(|tests| {
+        let count = &tests[kind as usize];
+        count.set(count.get() + 1);
+    });
+}
+
+/// Take the intersection tests counted on this thread so far, leaving out the kinds never tested.
+pub(crate) fn take_intersection_tests() -> Vec<(&'static str, u64)> {
+    INTERSECTION_TESTS.with(|tests| {
+        ObjectKind::ALL.iter()
+            .map(|kind| (kind.name(), tests[*kind as usize].take()))
+            .filter(|(_, count)| *count > 0)
+            .collect()
+    })
+}
+
+
+/// Statistics of a render, see `RenderEngine::stats`.
+///
+/// The scene has no acceleration structure, so there are no BVH node visits to count.
+#[derive(Debug, Clone, Default)]
+pub struct RenderStats {
+    /// Rays from the camera.
+    pub primary_rays: u64,
+    /// Rays continuing a path after a surface or medium interaction.
+    pub secondary_rays: u64,
+    /// Rays from points scattering in a medium towards the lights. Surfaces are shaded
+    /// without occlusion, so they cast none.
+    pub shadow_rays: u64,
+    /// Number of objects of each kind in the scene, in order of first appearance.
+    pub objects: Vec<(&'static str, u64)>,
+    /// Ray-object intersection tests per kind of object, in the order of `ObjectKind`. They include
+    /// the tests of the children of CSG nodes and instances and of the boundaries of volumes.
+    pub intersection_tests: Vec<(&'static str, u64)>,
+    /// Wall-clock time of each phase of the render, in order of first occurrence.
+    pub phases: Vec<(&'static str, Duration)>
+}
+
+impl RenderStats {
+    /// Add `duration` to the time of `phase`.
+    pub fn add_phase(&mut self, phase: &'static str, duration: Duration) {
+        match self.phases.iter_mut().find(|(name, _)| *name == phase) {
+            Some((_, total)) => *total += duration,
+            None => self.phases.push((phase, duration))
+        }
+    }
+
+    pub fn phase(&self, phase: &str) -> Duration {
+        self.phases.iter().find(|(name, _)| *name == phase).map_or(Duration::ZERO, |(_, duration)| *duration)
+    }
+
+    pub fn total_rays(&self) -> u64 {
+        self.primary_rays + self.secondary_rays + self.shadow_rays
+    }
+
+    /// Add `tests`, counted by `count_intersection_test`, to the intersection tests.
+    pub fn add_intersection_tests(&mut self, tests: Vec<(&'static str, u64)>) {
+        for (kind, count) in tests {
+            add_count(&mut self.intersection_tests, kind, count);
+        }
+    }
+
+    /// Mean number of rays per path from the camera, shadow rays aside.
+    pub fn average_path_length(&self) -> f64 {
+        if self.primary_rays == 0 {
+            return 0.0;
+        }
+        (self.primary_rays + self.secondary_rays) as f64 / self.primary_rays as f64
+    }
+
+    /// Rays cast per second of tracing.
+    pub fn rays_per_second(&self) -> f64 {
+        let seconds = self.phase("trace").as_secs_f64();
+        if seconds == 0.0 { 0.0 } else { self.total_rays() as f64 / seconds }
+    }
+
+    pub fn to_json(&self) -> String {
+        let entries = |values: Vec<String>| values.join(", ");
+        let tests = self.intersection_tests.iter().map(|(kind, count)| format!("\"{}\": {}", kind, count)).collect();
+        let phases = self.phases.iter().map(|(phase, duration)| format!("\"{}\": {}", phase, duration.as_secs_f64())).collect();
+        format!(
+            "{{\"primary_rays\": {}, \"secondary_rays\": {}, \"shadow_rays\": {}, \"intersection_tests\": {{{}}}, \
+             \"average_path_length\": {}, \"rays_per_second\": {}, \"phase_seconds\": {{{}}}}}",
+            self.primary_rays, self.secondary_rays, self.shadow_rays, entries(tests),
+            self.average_path_length(), self.rays_per_second(), entries(phases)
+        )
+    }
+}
+
+impl fmt::Display for RenderStats {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        writeln!(f, "Rays: {} primary, {} secondary, {} shadow", self.primary_rays, self.secondary_rays, self.shadow_rays)?;
+        writeln!(f, "Average path length: {:.2}", self.average_path_length())?;
+        writeln!(f, "Rays per second: {:.0}", self.rays_per_second())?;
+        writeln!(f, "Intersection tests:")?;
+        for (kind, count) in &self.intersection_tests {
+            writeln!(f, "  {:<16}{}", kind, count)?;
+        }
+        write!(f, "Time:")?;
+        for (phase, duration) in &self.phases {
+            write!(f, "\n  {:<16}{:.3} s", phase, duration.as_secs_f64())?;
+        }
+        Ok(())
+    }
+}
+
+/// Add `count` to the count of `kind` in `counts`.
+fn add_count(counts: &mut Vec<(&'static str, u64)>, kind: &'static str, count: u64) {
+    match counts.iter_mut().find(|(name, _)| *name == kind) {
+        Some((_, total)) => *total += count,
+        None => counts.push((kind, count))
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn intersection_tests_are_counted_per_kind() {
+        assert!(ObjectKind::ALL.iter().enumerate().all(|(index, kind)| *kind as usize == index), "kinds out of order");
+        take_intersection_tests();
+        count_intersection_test(ObjectKind::Torus);
+        count_intersection_test(ObjectKind::Sphere);
+        count_intersection_test(ObjectKind::Torus);
+        assert_eq!(take_intersection_tests(), vec![("sphere", 1), ("torus", 2)]);
+        assert!(take_intersection_tests().is_empty());
+    }
+}
@@ -0,0 +1,270 @@
+use crate::{
+    point::Point,
+    vector::{Vector, Matrix4, Quaternion},
+    ray::Ray,
+    utils
+};
+
+
+/// Affine transform from object space to world space, stored together with its inverse.
+#[derive(Debug, Copy, Clone, Default)]
+pub struct Transform {
+    matrix: Matrix4,
+    inverse: Matrix4
+}
+
+impl Transform {
+    /// Return `None` if `matrix` is not invertible.
+    pub fn new(matrix: Matrix4) -> Option<Self> {
+        matrix.inverse().map(|inverse| Self { matrix, inverse })
+    }
+
+    pub fn identity() -> Self {
+        Self::default()
+    }
+
+    pub fn translate(offset: Vector) -> Self {
+        Self {
+            matrix: Matrix4::translation(offset),
+            inverse: Matrix4::translation(-offset)
+        }
+    }
+
+    /// Scale by each component of `factor`, which must all be non-zero, see `validate`.
+    pub fn scale(factor: Vector) -> Self {
+        Self {
+            matrix: Matrix4::scaling(factor),
+            inverse: Matrix4::scaling(Vector::new(1.0 / factor.0, 1.0 / factor.1, 1.0 / factor.2))
+        }
+    }
+
+    /// Rotate counter-clockwise by `degrees` around `axis` passing through the origin.
+    pub fn rotate(axis: Vector, degrees: f64) -> Self {
+        let matrix = Matrix4::rotation(axis, utils::degrees_to_radians(degrees));
+        // Rotation matrices are orthogonal.
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    /// Rotate by the unit quaternion `rotation`.
+    pub fn from_quaternion(rotation: Quaternion) -> Self {
+        let matrix = rotation.to_matrix4();
+        Self { matrix, inverse: matrix.transpose() }
+    }
+
+    pub fn rotate_x(degrees: f64) -> Self {
+        Self::rotate(Vector::new(1.0, 0.0, 0.0), degrees)
+    }
+
+    pub fn rotate_y(degrees: f64) -> Self {
+        Self::rotate(Vector::new(0.0, 1.0, 0.0), degrees)
+    }
+
+    pub fn rotate_z(degrees: f64) -> Self {
+        Self::rotate(Vector::new(0.0, 0.0, 1.0), degrees)
+    }
+
+    /// Return the transform that applies `self` first and then `other`.
+    pub fn then(&self, other: &Self) -> Self {
+        Self {
+            matrix: other.matrix * self.matrix,
+            inverse: self.inverse * other.inverse
+        }
+    }
+
+    pub fn inverse(&self) -> Self {
+        Self { matrix: self.inverse, inverse: self.matrix }
+    }
+
+    pub fn matrix(&self) -> &Matrix4 {
+        &self.matrix
+    }
+
+    pub fn apply_point(&self, point: Point) -> Point {
+        point.transform_point(&self.matrix)
+    }
+
+    pub fn apply_vector(&self, vector: Vector) -> Vector {
+        vector.transform_direction(&self.matrix)
+    }
+
+    pub fn apply_normal(&self, normal: Vector) -> Vector {
+        normal.transform_normal(&self.inverse)
+    }
+
+    pub fn inverse_point(&self, point: Point) -> Point {
+        point.transform_point(&self.inverse)
+    }
+
+    pub fn inverse_vector(&self, vector: Vector) -> Vector {
+        vector.transform_direction(&self.inverse)
+    }
+
+    /// Fail if the transform isn't invertible, like a scale by zero, so that its inverse isn't finite.
+    pub fn validate(&self) -> Result<(), String> {
+        let finite = |matrix: &Matrix4| matrix.0.iter().flatten().all(|value| value.is_finite());
+        if !finite(&self.matrix) || !finite(&self.inverse) {
+            return Err(String::from("transform must be invertible, with non-zero scale factors"));
+        }
+        Ok(())
+    }
+
+    /// Transform a world space ray into object space.
+    /// Also return the factor converting world distances into object distances.
+    pub fn inverse_ray(&self, ray: Ray) -> (Ray, f64) {
+        let direction = self.inverse_vector(ray.direction);
+        (Ray::with_time(self.inverse_point(ray.origin), direction, ray.time), direction.magnitude())
+    }
+}
+
+
+/// Pose is a transform decomposed into a scale, followed by a rotation and a translation,
+/// so that it can be interpolated.
+#[derive(Debug, Copy, Clone)]
+pub struct Pose {
+    pub translation: Vector,
+    pub rotation: Quaternion,
+    pub scale: Vector
+}
+
+impl Default for Pose {
+    fn default() -> Self {
+        Self {
+            translation: Vector::new(0.0, 0.0, 0.0),
+            rotation: Quaternion::identity(),
+            scale: Vector::new(1.0, 1.0, 1.0)
+        }
+    }
+}
+
+impl Pose {
+    pub fn new(translation: Vector, rotation: Quaternion, scale: Vector) -> Self {
+        Self { translation, rotation, scale }
+    }
+
+    pub fn to_transform(self) -> Transform {
+        Transform::scale(self.scale)
+            .then(&Transform::from_quaternion(self.rotation))
+            .then(&Transform::translate(self.translation))
+    }
+
+    /// Interpolate linearly towards `other`, taking the shortest arc between rotations.
+    pub fn lerp(&self, other: &Self, t: f64) -> Self {
+        Self {
+            translation: self.translation + t * (other.translation - self.translation),
+            rotation: self.rotation.slerp(other.rotation, t),
+            scale: self.scale + t * (other.scale - self.scale)
+        }
+    }
+}
+
+
+/// Motion moves between two poses over a time interval, holding still outside of it.
+#[derive(Debug, Copy, Clone)]
+pub struct Motion {
+    start: Pose,
+    end: Pose,
+    time0: f64,
+    time1: f64
+}
+
+impl Motion {
+    pub fn new(start: Pose, time0: f64, end: Pose, time1: f64) -> Self {
+        Self { start, end, time0, time1 }
+    }
+
+    /// Fail if a pose isn't invertible, or a scale factor changes sign, passing through zero on the way.
+    pub fn validate(&self) -> Result<(), String> {
+        self.start.to_transform().validate()?;
+        self.end.to_transform().validate()?;
+        let (a, b) = (self.start.scale, self.end.scale);
+        if a.0 * b.0 <= 0.0 || a.1 * b.1 <= 0.0 || a.2 * b.2 <= 0.0 {
+            return Err(String::from("motion scale factors must not change sign"));
+        }
+        Ok(())
+    }
+
+    pub fn at(&self, time: f64) -> Transform {
+        let t = if self.time1 > self.time0 { (time - self.time0) / (self.time1 - self.time0) } else { 0.0 };
+        self.start.lerp(&self.end, utils::clamp(t, 0.0, 1.0)).to_transform()
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::rc::Rc;
+    use crate::{hitrecord::HitRecord, material::Dielectric, object::{Instance, Object, Sphere}};
+
+    const EPSILON: f64 = 1E-9;
+
+    fn assert_vector_eq(a: Vector, b: Vector) {
+        assert!((a - b).magnitude() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn transform() -> Transform {
+        Transform::scale(Vector::new(2.0, 0.5, 3.0))
+            .then(&Transform::rotate(Vector::new(1.0, 1.0, 0.0), 40.0))
+            .then(&Transform::translate(Vector::new(1.0, -2.0, 3.0)))
+    }
+
+    #[test]
+    fn inverse_undoes_transform() {
+        let (transform, point, vector) = (transform(), Point::new(0.3, -1.2, 4.0), Vector::new(-2.0, 0.5, 1.0));
+        assert_vector_eq(transform.inverse_point(transform.apply_point(point)), point);
+        assert_vector_eq(transform.apply_point(transform.inverse_point(point)), point);
+        assert_vector_eq(transform.inverse_vector(transform.apply_vector(vector)), vector);
+        assert_vector_eq(transform.inverse().apply_point(transform.apply_point(point)), point);
+    }
+
+    #[test]
+    fn then_applies_self_first() {
+        let point = Point::new(1.0, 0.0, 0.0);
+        let translate_first = Transform::translate(Vector::new(1.0, 0.0, 0.0)).then(&Transform::rotate_z(90.0));
+        let rotate_first = Transform::rotate_z(90.0).then(&Transform::translate(Vector::new(1.0, 0.0, 0.0)));
+        assert_vector_eq(translate_first.apply_point(point), Point::new(0.0, 2.0, 0.0));
+        assert_vector_eq(rotate_first.apply_point(point), Point::new(1.0, 1.0, 0.0));
+    }
+
+    #[test]
+    fn normals_stay_perpendicular_under_non_uniform_scale() {
+        let transform = Transform::scale(Vector::new(2.0, 1.0, 1.0));
+        // The surface x + y = 0 becomes x + 2y = 0.
+        let normal = transform.apply_normal(Vector::new(1.0, 1.0, 0.0)).normalize();
+        assert_vector_eq(normal, Vector::new(1.0, 2.0, 0.0).normalize());
+        assert!(normal.dot(transform.apply_vector(Vector::new(1.0, -1.0, 0.0))).abs() < EPSILON);
+    }
+
+    #[test]
+    fn zero_scale_is_invalid() {
+        assert!(transform().validate().is_ok());
+        assert!(Transform::scale(Vector::new(1.0, 0.0, 1.0)).validate().is_err());
+
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, Rc::new(Dielectric::default())));
+        assert!(Instance::new(Rc::clone(&sphere), transform()).validate().is_ok());
+        assert!(Instance::new(sphere, Transform::scale(Vector::new(0.0, 1.0, 1.0))).validate().is_err());
+    }
+
+    #[test]
+    fn instance_hits_through_rotation_and_scale() {
+        // A unit sphere stretched to 2 along x, turned so that its long axis lies along z, 5 away.
+        let sphere: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, Rc::new(Dielectric::default())));
+        let transform = Transform::scale(Vector::new(2.0, 1.0, 1.0))
+            .then(&Transform::rotate_y(90.0))
+            .then(&Transform::translate(Vector::new(0.0, 0.0, -5.0)));
+        let instance: Rc<dyn Object> = Rc::new(Instance::new(sphere, transform));
+
+        let hit = |origin: Point, direction: Vector| {
+            let mut hit_record = HitRecord::new(Rc::clone(&instance));
+            instance.hit(Ray::new(origin, direction), 0.0, utils::INFINITY, &mut hit_record)
+                .then_some((hit_record.distance, hit_record.normal))
+        };
+        let (distance, normal) = hit(Point::new(0.0, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0)).expect("no hit along the long axis");
+        assert!((distance - 3.0).abs() < EPSILON, "distance {}", distance);
+        assert_vector_eq(normal, Vector::new(0.0, 0.0, 1.0));
+        let (distance, normal) = hit(Point::new(0.0, 5.0, -5.0), Vector::new(0.0, -2.0, 0.0)).expect("no hit from above");
+        assert!((distance - 4.0).abs() < EPSILON, "distance {}", distance);
+        assert_vector_eq(normal, Vector::new(0.0, 1.0, 0.0));
+        assert!(hit(Point::new(1.5, 0.0, 0.0), Vector::new(0.0, 0.0, -1.0)).is_none());
+    }
+}
@@ -1,8 +1,5 @@
-use rand::Rng;
-
-
 pub const INFINITY: f64 = f64::INFINITY;
-pub const PI: f64 = 3.1415926535897932385;
+pub const PI: f64 = std::f64::consts::PI;
 
 
 #[inline]
@@ -15,7 +12,169 @@ pub fn clamp(x: f64, min: f64, max: f64) -> f64 {
     f64::max(f64::min(x, max), min)
 }
 
-pub fn random_double(min: f64, max: f64) -> f64 {
-    let mut rng = rand::thread_rng();
-    rng.gen_range(min..max)
-}
\ No newline at end of file
+/// Real roots of `a x^2 + b x + c = 0` in ascending order.
+pub fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    if a == 0.0 {
+        return if b == 0.0 { vec![] } else { vec![-c / b] };
+    }
+
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant < 0.0 {
+        return vec![];
+    }
+
+    // Avoid cancellation by computing the larger root first.
+    let q = -0.5 * (b + b.signum() * discriminant.sqrt());
+    let mut roots = if q == 0.0 { vec![0.0] } else { vec![q / a, c / q] };
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of `x^3 + a x^2 + b x + c = 0` in ascending order.
+pub fn solve_cubic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    const DELTA: f64 = 1E-12;
+
+    // Substitute x = y - a/3 to get the depressed cubic y^3 + 3p y + 2q = 0.
+    let p = (b - a * a / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * a * a * a - a * b / 3.0 + c) / 2.0;
+    let discriminant = q * q + p * p * p;
+
+    let mut roots = if discriminant.abs() < DELTA {
+        if q.abs() < DELTA {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // Three real roots.
+        let phi = (-q / (-p * p * p).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let t = 2.0 * (-p).sqrt();
+        vec![t * phi.cos(), -t * (phi + PI / 3.0).cos(), -t * (phi - PI / 3.0).cos()]
+    } else {
+        let sqrt = discriminant.sqrt();
+        vec![(sqrt - q).cbrt() - (sqrt + q).cbrt()]
+    };
+
+    for root in roots.iter_mut() {
+        *root -= a / 3.0;
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+/// Real roots of `x^4 + a x^3 + b x^2 + c x + d = 0` in ascending order, using Ferrari's method.
+pub fn solve_quartic(a: f64, b: f64, c: f64, d: f64) -> Vec<f64> {
+    const DELTA: f64 = 1E-12;
+
+    // Substitute x = y - a/4 to get the depressed quartic y^4 + p y^2 + q y + r = 0.
+    let aa = a * a;
+    let p = b - 3.0 / 8.0 * aa;
+    let q = aa * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * aa * aa + aa * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if r.abs() < DELTA {
+        // y (y^3 + p y + q) = 0
+        let mut roots = solve_cubic(0.0, p, q);
+        roots.push(0.0);
+        roots
+    } else {
+        // The largest root of the resolvent cubic splits the quartic into two real quadratics.
+        let z = *solve_cubic(-p / 2.0, -r, r * p / 2.0 - q * q / 8.0).last().unwrap();
+
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        if u < -DELTA || v < -DELTA {
+            return vec![];
+        }
+        let u = u.max(0.0).sqrt();
+        let v = if q < 0.0 { -v.max(0.0).sqrt() } else { v.max(0.0).sqrt() };
+
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.extend(solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    for root in roots.iter_mut() {
+        *root -= a / 4.0;
+
+        // Polish with Newton's method, the closed form loses precision for distant roots.
+        for _ in 0..2 {
+            let x = *root;
+            let f = (((x + a) * x + b) * x + c) * x + d;
+            let df = ((4.0 * x + 3.0 * a) * x + 2.0 * b) * x + c;
+            if df != 0.0 {
+                *root -= f / df;
+            }
+        }
+    }
+    roots.sort_by(f64::total_cmp);
+    roots
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Check that `roots` are `expected`, in order and within `epsilon`.
+    fn assert_roots_eq(roots: Vec<f64>, expected: &[f64], epsilon: f64) {
+        assert_eq!(roots.len(), expected.len(), "{:?} != {:?}", roots, expected);
+        for (root, expected) in roots.iter().zip(expected) {
+            assert!((root - expected).abs() < epsilon, "{:?} != {:?}", roots, expected);
+        }
+    }
+
+    #[test]
+    fn quadratic_roots() {
+        assert_roots_eq(solve_quadratic(1.0, -3.0, 2.0), &[1.0, 2.0], 1E-12);
+        assert_roots_eq(solve_quadratic(1.0, -2.0, 1.0), &[1.0, 1.0], 1E-12);
+        assert_roots_eq(solve_quadratic(1.0, 0.0, 1.0), &[], 0.0);
+        assert_roots_eq(solve_quadratic(1.0, 0.0, 0.0), &[0.0], 1E-12);
+    }
+
+    #[test]
+    fn quadratic_with_vanishing_leading_coefficient() {
+        assert_roots_eq(solve_quadratic(0.0, 2.0, -1.0), &[0.5], 1E-12);
+        assert_roots_eq(solve_quadratic(0.0, 0.0, 1.0), &[], 0.0);
+        // The small root stays precise while the other one runs off.
+        let roots = solve_quadratic(1E-12, 1.0, -1.0);
+        assert_eq!(roots.len(), 2);
+        assert!(roots[0] < -1E11 && (roots[1] - 1.0).abs() < 1E-9, "{:?}", roots);
+    }
+
+    #[test]
+    fn cubic_roots() {
+        // (x - 1)(x - 2)(x - 3)
+        assert_roots_eq(solve_cubic(-6.0, 11.0, -6.0), &[1.0, 2.0, 3.0], 1E-9);
+        // (x - 1)(x^2 + 1)
+        assert_roots_eq(solve_cubic(-1.0, 1.0, -1.0), &[1.0], 1E-9);
+        // Depressed: x^3 - x
+        assert_roots_eq(solve_cubic(0.0, -1.0, 0.0), &[-1.0, 0.0, 1.0], 1E-9);
+    }
+
+    #[test]
+    fn cubic_repeated_roots() {
+        // (x - 1)^2 (x + 2)
+        assert_roots_eq(solve_cubic(0.0, -3.0, 2.0), &[-2.0, 1.0], 1E-9);
+        // (x - 2)^3
+        assert_roots_eq(solve_cubic(-6.0, 12.0, -8.0), &[2.0], 1E-9);
+    }
+
+    #[test]
+    fn quartic_roots() {
+        // (x - 1)(x - 2)(x - 3)(x - 4)
+        assert_roots_eq(solve_quartic(-10.0, 35.0, -50.0, 24.0), &[1.0, 2.0, 3.0, 4.0], 1E-9);
+        // (x^2 - 1)(x^2 - 4), without odd terms.
+        assert_roots_eq(solve_quartic(0.0, -5.0, 0.0, 4.0), &[-2.0, -1.0, 1.0, 2.0], 1E-9);
+        assert_roots_eq(solve_quartic(0.0, 0.0, 0.0, 1.0), &[], 0.0);
+    }
+
+    #[test]
+    fn quartic_depressed_and_repeated_roots() {
+        // Depressed with no constant term: x^2 (x - 1)(x + 1)
+        assert_roots_eq(solve_quartic(0.0, -1.0, 0.0, 0.0), &[-1.0, 0.0, 0.0, 1.0], 1E-9);
+        // (x - 1)^2 (x - 3)^2
+        assert_roots_eq(solve_quartic(-8.0, 22.0, -24.0, 9.0), &[1.0, 1.0, 3.0, 3.0], 1E-6);
+    }
+}
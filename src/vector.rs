@@ -47,7 +47,7 @@ impl Vector {
         // let cos_theta = f64::min(self.dot(-normal), 1.0);
         let r_perpendicular: Self = refraction_ratio * (*self + cos_theta * normal);
         // let r_out_parallel = -(1.0 - r_out_perp.dot(r_out_perp)).abs().sqrt() * normal;
-        let r_parallel = -1.0 * (1.0 - r_perpendicular.magnitude().powi(2)).abs().sqrt() * normal;
+        let r_parallel = -(1.0 - r_perpendicular.magnitude().powi(2)).abs().sqrt() * normal;
         r_perpendicular + r_parallel
     }
 
@@ -63,6 +63,22 @@ impl Vector {
         const DELTA: f64 = 1E-3;
         self.0.abs() < DELTA && self.1.abs() < DELTA && self.2.abs() < DELTA
     }
+
+    /// Transform as a point, so translation applies.
+    pub fn transform_point(&self, matrix: &Matrix4) -> Self {
+        matrix.multiply(*self, 1.0)
+    }
+
+    /// Transform as a direction, so translation is ignored.
+    pub fn transform_direction(&self, matrix: &Matrix4) -> Self {
+        matrix.multiply(*self, 0.0)
+    }
+
+    /// Transform as a surface normal. `inverse` is the inverse of the matrix transforming points,
+    /// since normals transform with the inverse transpose to stay perpendicular to the surface.
+    pub fn transform_normal(&self, inverse: &Matrix4) -> Self {
+        inverse.transpose().multiply(*self, 0.0)
+    }
 }
 
 /// Struct methods
@@ -85,6 +101,337 @@ impl Vector {
 }
 
 
+
+/// Row-major 3x3 matrix acting on column vectors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix3(pub [[f64; 3]; 3]);
+
+impl Matrix3 {
+    pub fn identity() -> Self {
+        Self([
+            [1.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0],
+            [0.0, 0.0, 1.0]
+        ])
+    }
+
+    pub fn from_columns(x: Vector, y: Vector, z: Vector) -> Self {
+        Self([
+            [x.0, y.0, z.0],
+            [x.1, y.1, z.1],
+            [x.2, y.2, z.2]
+        ])
+    }
+
+    /// Orthonormal frame whose columns are two tangents and the normalized `axis`.
+    pub fn from_axis(axis: Vector) -> Self {
+        let axis = axis.normalize();
+        let helper = if axis.0.abs() > 0.9 { Vector(0.0, 1.0, 0.0) } else { Vector(1.0, 0.0, 0.0) };
+        let tangent = axis.cross(helper).normalize();
+        Self::from_columns(tangent, axis.cross(tangent), axis)
+    }
+
+    pub fn column(&self, index: usize) -> Vector {
+        Vector(self.0[0][index], self.0[1][index], self.0[2][index])
+    }
+
+    /// Rotate counter-clockwise by `angle` radians around `axis`.
+    pub fn rotation(axis: Vector, angle: f64) -> Self {
+        let Vector(x, y, z) = axis.normalize();
+        let (sin, cos) = angle.sin_cos();
+        let t = 1.0 - cos;
+        Self([
+            [t * x * x + cos,     t * x * y - sin * z, t * x * z + sin * y],
+            [t * x * y + sin * z, t * y * y + cos,     t * y * z - sin * x],
+            [t * x * z - sin * y, t * y * z + sin * x, t * z * z + cos    ]
+        ])
+    }
+
+    pub fn transpose(&self) -> Self {
+        Self::from_columns(
+            Vector(self.0[0][0], self.0[0][1], self.0[0][2]),
+            Vector(self.0[1][0], self.0[1][1], self.0[1][2]),
+            Vector(self.0[2][0], self.0[2][1], self.0[2][2])
+        )
+    }
+
+    pub fn determinant(&self) -> f64 {
+        self.column(0).dot(self.column(1).cross(self.column(2)))
+    }
+
+    /// Return `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let determinant = self.determinant();
+        if determinant.abs() < 1E-12 {
+            return None;
+        }
+
+        // Rows of the inverse are the cross products of the columns, scaled by the determinant.
+        let (x, y, z) = (self.column(0), self.column(1), self.column(2));
+        Some(Self::from_columns(
+            y.cross(z) / determinant,
+            z.cross(x) / determinant,
+            x.cross(y) / determinant
+        ).transpose())
+    }
+}
+
+
+/// Row-major 4x4 matrix acting on column vectors.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Matrix4(pub [[f64; 4]; 4]);
+
+impl Matrix4 {
+    pub fn identity() -> Self {
+        Self([
+            [1.0, 0.0, 0.0, 0.0],
+            [0.0, 1.0, 0.0, 0.0],
+            [0.0, 0.0, 1.0, 0.0],
+            [0.0, 0.0, 0.0, 1.0]
+        ])
+    }
+
+    /// Affine matrix with `linear` as the upper-left block followed by `translation`.
+    pub fn from_parts(linear: Matrix3, translation: Vector) -> Self {
+        let mut result = Self::identity();
+        for i in 0..3 {
+            result.0[i][..3].copy_from_slice(&linear.0[i]);
+            result.0[i][3] = translation[i];
+        }
+        result
+    }
+
+    /// Return the upper-left 3x3 block.
+    pub fn linear(&self) -> Matrix3 {
+        let mut result = Matrix3::identity();
+        for i in 0..3 {
+            result.0[i].copy_from_slice(&self.0[i][..3]);
+        }
+        result
+    }
+
+    pub fn translation(offset: Vector) -> Self {
+        Self::from_parts(Matrix3::identity(), offset)
+    }
+
+    pub fn scaling(factor: Vector) -> Self {
+        Self::from_parts(
+            Matrix3::from_columns(
+                Vector(factor.0, 0.0, 0.0),
+                Vector(0.0, factor.1, 0.0),
+                Vector(0.0, 0.0, factor.2)
+            ),
+            Vector::default()
+        )
+    }
+
+    /// Rotate counter-clockwise by `angle` radians around `axis` passing through the origin.
+    pub fn rotation(axis: Vector, angle: f64) -> Self {
+        Self::from_parts(Matrix3::rotation(axis, angle), Vector::default())
+    }
+
+    pub fn rotation_x(angle: f64) -> Self {
+        Self::rotation(Vector(1.0, 0.0, 0.0), angle)
+    }
+
+    pub fn rotation_y(angle: f64) -> Self {
+        Self::rotation(Vector(0.0, 1.0, 0.0), angle)
+    }
+
+    pub fn rotation_z(angle: f64) -> Self {
+        Self::rotation(Vector(0.0, 0.0, 1.0), angle)
+    }
+
+    /// Camera to world matrix for a camera at `eye` looking at `target`.
+    /// The camera looks down its local -z axis with +y as up, like the default view.
+    pub fn look_at(eye: Vector, target: Vector, up: Vector) -> Self {
+        let forward = (target - eye).normalize();
+        let right = forward.cross(up).normalize();
+        let true_up = right.cross(forward);
+        Self::from_parts(Matrix3::from_columns(right, true_up, -forward), eye)
+    }
+
+    pub fn transpose(&self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = self.0[j][i];
+            }
+        }
+        Self(result)
+    }
+
+    /// Invert using Gauss-Jordan elimination with partial pivoting.
+    /// Return `None` if the matrix is singular.
+    pub fn inverse(&self) -> Option<Self> {
+        let mut a = self.0;
+        let mut inverse = Self::identity().0;
+
+        for column in 0..4 {
+            let pivot = (column..4)
+                .max_by(|&i, &j| a[i][column].abs().total_cmp(&a[j][column].abs()))
+                .unwrap();
+            if a[pivot][column].abs() < 1E-12 {
+                return None;
+            }
+            a.swap(column, pivot);
+            inverse.swap(column, pivot);
+
+            let scale = 1.0 / a[column][column];
+            for k in 0..4 {
+                a[column][k] *= scale;
+                inverse[column][k] *= scale;
+            }
+
+            for row in 0..4 {
+                if row == column {
+                    continue;
+                }
+                let factor = a[row][column];
+                for k in 0..4 {
+                    a[row][k] -= factor * a[column][k];
+                    inverse[row][k] -= factor * inverse[column][k];
+                }
+            }
+        }
+        Some(Self(inverse))
+    }
+
+    /// Multiply with the homogeneous vector `(v, w)`, ignoring the projective row.
+    fn multiply(&self, v: Vector, w: f64) -> Vector {
+        let m = &self.0;
+        Vector(
+            m[0][0] * v.0 + m[0][1] * v.1 + m[0][2] * v.2 + m[0][3] * w,
+            m[1][0] * v.0 + m[1][1] * v.1 + m[1][2] * v.2 + m[1][3] * w,
+            m[2][0] * v.0 + m[2][1] * v.1 + m[2][2] * v.2 + m[2][3] * w
+        )
+    }
+}
+
+
+/// Quaternion `w + xi + yj + zk`. Unit quaternions represent rotations.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub struct Quaternion {
+    pub w: f64,
+    pub x: f64,
+    pub y: f64,
+    pub z: f64
+}
+
+impl Quaternion {
+    pub fn new(w: f64, x: f64, y: f64, z: f64) -> Self {
+        Self { w, x, y, z }
+    }
+
+    pub fn identity() -> Self {
+        Self::new(1.0, 0.0, 0.0, 0.0)
+    }
+
+    /// Rotation counter-clockwise by `angle` radians around `axis`.
+    pub fn from_axis_angle(axis: Vector, angle: f64) -> Self {
+        let Vector(x, y, z) = axis.normalize();
+        let (sin, cos) = (0.5 * angle).sin_cos();
+        Self::new(cos, x * sin, y * sin, z * sin)
+    }
+
+    /// Rotation by `x`, then `y`, then `z` radians around the respective axes.
+    pub fn from_euler(x: f64, y: f64, z: f64) -> Self {
+        Self::from_axis_angle(Vector(0.0, 0.0, 1.0), z)
+            * Self::from_axis_angle(Vector(0.0, 1.0, 0.0), y)
+            * Self::from_axis_angle(Vector(1.0, 0.0, 0.0), x)
+    }
+
+    /// Convert an orthonormal rotation matrix into a unit quaternion.
+    pub fn from_matrix(matrix: &Matrix3) -> Self {
+        let m = &matrix.0;
+        let trace = m[0][0] + m[1][1] + m[2][2];
+
+        // Pick the largest diagonal term to keep the square root well conditioned.
+        let q = if trace > 0.0 {
+            let s = 2.0 * (trace + 1.0).sqrt();
+            Self::new(0.25 * s, (m[2][1] - m[1][2]) / s, (m[0][2] - m[2][0]) / s, (m[1][0] - m[0][1]) / s)
+        } else if m[0][0] > m[1][1] && m[0][0] > m[2][2] {
+            let s = 2.0 * (1.0 + m[0][0] - m[1][1] - m[2][2]).sqrt();
+            Self::new((m[2][1] - m[1][2]) / s, 0.25 * s, (m[0][1] + m[1][0]) / s, (m[0][2] + m[2][0]) / s)
+        } else if m[1][1] > m[2][2] {
+            let s = 2.0 * (1.0 + m[1][1] - m[0][0] - m[2][2]).sqrt();
+            Self::new((m[0][2] - m[2][0]) / s, (m[0][1] + m[1][0]) / s, 0.25 * s, (m[1][2] + m[2][1]) / s)
+        } else {
+            let s = 2.0 * (1.0 + m[2][2] - m[0][0] - m[1][1]).sqrt();
+            Self::new((m[1][0] - m[0][1]) / s, (m[0][2] + m[2][0]) / s, (m[1][2] + m[2][1]) / s, 0.25 * s)
+        };
+        q.normalize()
+    }
+
+    pub fn dot(&self, other: Self) -> f64 {
+        self.w * other.w + self.x * other.x + self.y * other.y + self.z * other.z
+    }
+
+    pub fn magnitude(&self) -> f64 {
+        self.dot(*self).sqrt()
+    }
+
+    pub fn normalize(&self) -> Self {
+        let magnitude = self.magnitude();
+        Self::new(self.w / magnitude, self.x / magnitude, self.y / magnitude, self.z / magnitude)
+    }
+
+    pub fn conjugate(&self) -> Self {
+        Self::new(self.w, -self.x, -self.y, -self.z)
+    }
+
+    pub fn inverse(&self) -> Self {
+        let norm = self.dot(*self);
+        let c = self.conjugate();
+        Self::new(c.w / norm, c.x / norm, c.y / norm, c.z / norm)
+    }
+
+    /// Rotate `vector` by this unit quaternion.
+    pub fn rotate(&self, vector: Vector) -> Vector {
+        let u = Vector(self.x, self.y, self.z);
+        let t = 2.0 * u.cross(vector);
+        vector + self.w * t + u.cross(t)
+    }
+
+    pub fn to_matrix3(self) -> Matrix3 {
+        Matrix3::from_columns(
+            self.rotate(Vector(1.0, 0.0, 0.0)),
+            self.rotate(Vector(0.0, 1.0, 0.0)),
+            self.rotate(Vector(0.0, 0.0, 1.0))
+        )
+    }
+
+    pub fn to_matrix4(self) -> Matrix4 {
+        Matrix4::from_parts(self.to_matrix3(), Vector::default())
+    }
+
+    /// Spherical linear interpolation between unit quaternions along the shortest arc.
+    pub fn slerp(&self, other: Self, t: f64) -> Self {
+        let mut cos = self.dot(other);
+        let mut other = other;
+        if cos < 0.0 {
+            cos = -cos;
+            other = Self::new(-other.w, -other.x, -other.y, -other.z);
+        }
+
+        // Fall back to linear interpolation when the rotations are nearly identical.
+        let (a, b) = if cos > 0.9995 {
+            (1.0 - t, t)
+        } else {
+            let theta = cos.acos();
+            let sin = theta.sin();
+            (((1.0 - t) * theta).sin() / sin, (t * theta).sin() / sin)
+        };
+
+        Self::new(
+            a * self.w + b * other.w,
+            a * self.x + b * other.x,
+            a * self.y + b * other.y,
+            a * self.z + b * other.z
+        ).normalize()
+    }
+}
+
 // Default trait and operator overloading. Nothing special below this line.
 
 impl Default for Vector {
@@ -188,3 +535,204 @@ impl ops::IndexMut<usize> for Vector {
         }
     }
 }
+
+
+impl Default for Matrix3 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl ops::Mul for Matrix3 {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        let mut result = [[0.0; 3]; 3];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..3).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Self(result)
+    }
+}
+
+impl ops::Mul<Vector> for Matrix3 {
+    type Output = Vector;
+    fn mul(self, other: Vector) -> Vector {
+        let m = &self.0;
+        Vector(
+            m[0][0] * other.0 + m[0][1] * other.1 + m[0][2] * other.2,
+            m[1][0] * other.0 + m[1][1] * other.1 + m[1][2] * other.2,
+            m[2][0] * other.0 + m[2][1] * other.1 + m[2][2] * other.2
+        )
+    }
+}
+
+impl Default for Matrix4 {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl ops::Mul for Matrix4 {
+    type Output = Self;
+    fn mul(self, other: Self) -> Self {
+        let mut result = [[0.0; 4]; 4];
+        for (i, row) in result.iter_mut().enumerate() {
+            for (j, value) in row.iter_mut().enumerate() {
+                *value = (0..4).map(|k| self.0[i][k] * other.0[k][j]).sum();
+            }
+        }
+        Self(result)
+    }
+}
+
+impl Default for Quaternion {
+    fn default() -> Self {
+        Self::identity()
+    }
+}
+
+impl ops::Mul for Quaternion {
+    type Output = Self;
+    /// Hamilton product. `a * b` rotates by `b` first and then by `a`.
+    fn mul(self, other: Self) -> Self {
+        Self::new(
+            self.w * other.w - self.x * other.x - self.y * other.y - self.z * other.z,
+            self.w * other.x + self.x * other.w + self.y * other.z - self.z * other.y,
+            self.w * other.y - self.x * other.z + self.y * other.w + self.z * other.x,
+            self.w * other.z + self.x * other.y - self.y * other.x + self.z * other.w
+        )
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::f64::consts::FRAC_PI_2;
+
+    const EPSILON: f64 = 1E-9;
+
+    fn assert_vector_eq(a: Vector, b: Vector) {
+        assert!((a - b).magnitude() < EPSILON, "{:?} != {:?}", a, b);
+    }
+
+    fn assert_matrix4_eq(a: Matrix4, b: Matrix4) {
+        for i in 0..4 {
+            for j in 0..4 {
+                assert!((a.0[i][j] - b.0[i][j]).abs() < EPSILON, "{:?} != {:?}", a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn matrix4_inverse_undoes_matrix() {
+        let m = Matrix4::translation(Vector(1.0, -2.0, 3.0))
+            * Matrix4::rotation(Vector(1.0, 1.0, 0.0), 0.7)
+            * Matrix4::scaling(Vector(2.0, 0.5, 3.0));
+        assert_matrix4_eq(m * m.inverse().unwrap(), Matrix4::identity());
+        assert_matrix4_eq(m.inverse().unwrap() * m, Matrix4::identity());
+    }
+
+    #[test]
+    fn matrix4_singular_has_no_inverse() {
+        assert!(Matrix4::scaling(Vector(1.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn matrix4_transpose_swaps_rows_and_columns() {
+        let m = Matrix4::translation(Vector(1.0, 2.0, 3.0));
+        assert_eq!(m.transpose().0[3], [1.0, 2.0, 3.0, 1.0]);
+        assert_eq!(m.transpose().transpose(), m);
+    }
+
+    #[test]
+    fn matrix4_composition_applies_right_to_left() {
+        let m = Matrix4::translation(Vector(1.0, 0.0, 0.0)) * Matrix4::scaling(Vector(2.0, 2.0, 2.0));
+        assert_vector_eq(Vector(1.0, 1.0, 1.0).transform_point(&m), Vector(3.0, 2.0, 2.0));
+        assert_vector_eq(Vector(1.0, 1.0, 1.0).transform_direction(&m), Vector(2.0, 2.0, 2.0));
+    }
+
+    #[test]
+    fn matrix4_rotation_is_counter_clockwise() {
+        let m = Matrix4::rotation_z(FRAC_PI_2);
+        assert_vector_eq(Vector(1.0, 0.0, 0.0).transform_direction(&m), Vector(0.0, 1.0, 0.0));
+        let m = Matrix4::rotation_x(FRAC_PI_2);
+        assert_vector_eq(Vector(0.0, 1.0, 0.0).transform_direction(&m), Vector(0.0, 0.0, 1.0));
+        let m = Matrix4::rotation_y(FRAC_PI_2);
+        assert_vector_eq(Vector(0.0, 0.0, 1.0).transform_direction(&m), Vector(1.0, 0.0, 0.0));
+    }
+
+    #[test]
+    fn matrix4_look_at_points_camera_at_target() {
+        let eye = Vector(1.0, 2.0, 3.0);
+        let target = Vector(-2.0, 0.0, 1.0);
+        let m = Matrix4::look_at(eye, target, Vector(0.0, 1.0, 0.0));
+        assert_vector_eq(Vector::default().transform_point(&m), eye);
+        assert_vector_eq(Vector(0.0, 0.0, -1.0).transform_direction(&m), (target - eye).normalize());
+        assert!(Vector(0.0, 1.0, 0.0).transform_direction(&m).1 > 0.0);
+    }
+
+    #[test]
+    fn normal_stays_perpendicular_under_non_uniform_scaling() {
+        let m = Matrix4::scaling(Vector(4.0, 1.0, 1.0));
+        let tangent = Vector(1.0, -1.0, 0.0);
+        let normal = Vector(1.0, 1.0, 0.0);
+        let tangent = tangent.transform_direction(&m);
+        let normal = normal.transform_normal(&m.inverse().unwrap());
+        assert!(tangent.dot(normal).abs() < EPSILON);
+    }
+
+    #[test]
+    fn matrix3_inverse_and_determinant() {
+        let m = Matrix3::rotation(Vector(0.0, 1.0, 1.0), 1.2) * Matrix3::from_columns(
+            Vector(2.0, 0.0, 0.0), Vector(1.0, 3.0, 0.0), Vector(0.0, 0.0, 0.5)
+        );
+        assert!((m.determinant() - 3.0).abs() < EPSILON);
+        let identity = m * m.inverse().unwrap();
+        for i in 0..3 {
+            assert_vector_eq(identity.column(i), Matrix3::identity().column(i));
+        }
+        assert!(Matrix3::from_columns(Vector(1.0, 0.0, 0.0), Vector(2.0, 0.0, 0.0), Vector(0.0, 0.0, 1.0)).inverse().is_none());
+    }
+
+    #[test]
+    fn quaternion_rotation_matches_matrix() {
+        let axis = Vector(1.0, 2.0, -1.0);
+        let q = Quaternion::from_axis_angle(axis, 0.9);
+        let m = Matrix3::rotation(axis, 0.9);
+        let v = Vector(0.3, -1.0, 2.0);
+        assert_vector_eq(q.rotate(v), m * v);
+        assert_vector_eq(q.to_matrix3() * v, m * v);
+    }
+
+    #[test]
+    fn quaternion_composition_and_inverse() {
+        let a = Quaternion::from_axis_angle(Vector(0.0, 0.0, 1.0), FRAC_PI_2);
+        let b = Quaternion::from_axis_angle(Vector(1.0, 0.0, 0.0), FRAC_PI_2);
+        let v = Vector(0.0, 1.0, 0.0);
+        assert_vector_eq((a * b).rotate(v), a.rotate(b.rotate(v)));
+        assert_vector_eq((a * a.inverse()).rotate(v), v);
+        assert_vector_eq(a.conjugate().rotate(a.rotate(v)), v);
+    }
+
+    #[test]
+    fn quaternion_from_euler_and_matrix_round_trip() {
+        let q = Quaternion::from_euler(0.3, -1.1, 2.5);
+        let r = Quaternion::from_matrix(&q.to_matrix3());
+        let v = Vector(1.0, 2.0, 3.0);
+        assert_vector_eq(q.rotate(v), r.rotate(v));
+        assert!((q.magnitude() - 1.0).abs() < EPSILON);
+    }
+
+    #[test]
+    fn quaternion_slerp_interpolates_angle() {
+        let a = Quaternion::identity();
+        let b = Quaternion::from_axis_angle(Vector(0.0, 1.0, 0.0), FRAC_PI_2);
+        let half = a.slerp(b, 0.5);
+        let expected = Quaternion::from_axis_angle(Vector(0.0, 1.0, 0.0), FRAC_PI_2 / 2.0);
+        assert!((half.dot(expected).abs() - 1.0).abs() < EPSILON);
+        assert!((a.slerp(b, 1.0).dot(b) - 1.0).abs() < EPSILON);
+    }
+}
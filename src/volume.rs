@@ -0,0 +1,412 @@
+use std::rc::Rc;
+
+use crate::{
+    color::Color,
+    vector::{Vector, Matrix3},
+    ray::Ray,
+    point::Point,
+    object::{self, Object},
+    hitrecord::HitRecord,
+    transform::Transform,
+    voxel::DensityGrid,
+    sampler::Sampler,
+    utils
+};
+
+
+/// Henyey-Greenstein phase function. Positive `g` favors forward scattering,
+/// negative `g` favors back scattering and zero scatters uniformly.
+#[derive(Debug, Copy, Clone)]
+pub struct HenyeyGreenstein {
+    g: f64
+}
+
+impl HenyeyGreenstein {
+    pub fn new(g: f64) -> Self {
+        Self { g: utils::clamp(g, -0.999, 0.999) }
+    }
+
+    /// Probability density of turning by an angle of `cos_theta` from the direction of travel.
+    pub fn evaluate(&self, cos_theta: f64) -> f64 {
+        let g = self.g;
+        let denominator = 1.0 + g * g - 2.0 * g * cos_theta;
+        (1.0 - g * g) / (4.0 * utils::PI * denominator * denominator.sqrt())
+    }
+
+    /// Sample a new direction of travel for light traveling along `direction`,
+    /// from two uniform numbers in `[0, 1)`.
+    pub fn sample(&self, direction: Vector, u1: f64, u2: f64) -> Vector {
+        let g = self.g;
+        let cos_theta = if g.abs() < 1E-3 {
+            1.0 - 2.0 * u1
+        } else {
+            let s = (1.0 - g * g) / (1.0 - g + 2.0 * g * u1);
+            (1.0 + g * g - s * s) / (2.0 * g)
+        };
+        let sin_theta = (1.0 - cos_theta * cos_theta).max(0.0).sqrt();
+        let (sin_phi, cos_phi) = (2.0 * utils::PI * u2).sin_cos();
+
+        Matrix3::from_axis(direction) * Vector::new(sin_theta * cos_phi, sin_theta * sin_phi, cos_theta)
+    }
+}
+
+
+/// Outcome of sampling the first collision of a ray with a volume.
+/// `weight` is the throughput divided by the probability of the outcome.
+#[derive(Debug, Copy, Clone)]
+pub enum VolumeSample {
+    /// The ray scatters at `distance`.
+    Scatter { distance: f64, weight: Color },
+    /// The ray passes through without scattering.
+    Pass { weight: Color }
+}
+
+
+/// Homogeneous medium with per-channel absorption and scattering coefficients per unit distance.
+#[derive(Debug, Copy, Clone)]
+pub struct Medium {
+    pub absorption: Color,
+    pub scattering: Color,
+    pub phase: HenyeyGreenstein
+}
+
+impl Medium {
+    pub fn new(absorption: Color, scattering: Color, g: f64) -> Self {
+        Self { absorption, scattering, phase: HenyeyGreenstein::new(g) }
+    }
+
+    /// Fail if a coefficient is negative.
+    pub fn validate(&self) -> Result<(), String> {
+        let (a, s) = (self.absorption, self.scattering);
+        if [a.0, a.1, a.2, s.0, s.1, s.2].iter().all(|coefficient| *coefficient >= 0.0) {
+            Ok(())
+        } else {
+            Err(String::from("medium coefficients must not be negative"))
+        }
+    }
+
+    pub fn extinction(&self) -> Color {
+        self.absorption + self.scattering
+    }
+
+    /// Fraction of light transmitted over `distance`.
+    pub fn transmittance(&self, distance: f64) -> Color {
+        let extinction = self.extinction();
+        Color::new(
+            attenuation(extinction.0, distance),
+            attenuation(extinction.1, distance),
+            attenuation(extinction.2, distance)
+        )
+    }
+
+    /// Sample a collision within `length` of travel, from a uniform number `u` in `[0, 1)`.
+    /// Distances are drawn using the mean extinction of the channels and the weight corrects for each channel.
+    pub fn sample(&self, length: f64, u: f64) -> VolumeSample {
+        let extinction = self.extinction();
+        let mean = (extinction.0 + extinction.1 + extinction.2) / 3.0;
+        if mean <= 0.0 {
+            return VolumeSample::Pass { weight: Color::new(1.0, 1.0, 1.0) };
+        }
+
+        let distance = -(1.0 - u).ln() / mean;
+        if distance < length {
+            let pdf = mean * attenuation(mean, distance);
+            VolumeSample::Scatter { distance, weight: self.transmittance(distance) * self.scattering / pdf }
+        } else {
+            VolumeSample::Pass { weight: self.pass_weight(length) }
+        }
+    }
+
+    /// Weight of passing `length` of travel without a collision drawn by `sample`.
+    pub fn pass_weight(&self, length: f64) -> Color {
+        let extinction = self.extinction();
+        let mean = (extinction.0 + extinction.1 + extinction.2) / 3.0;
+        if mean <= 0.0 {
+            return Color::new(1.0, 1.0, 1.0);
+        }
+        self.transmittance(length) / attenuation(mean, length)
+    }
+}
+
+/// Return `exp(-coefficient * distance)`, treating an empty medium as fully transparent at any distance.
+fn attenuation(coefficient: f64, distance: f64) -> f64 {
+    if coefficient == 0.0 { 1.0 } else { (-coefficient * distance).exp() }
+}
+
+
+pub trait Volume {
+    /// Sample the first collision of `ray` with the volume in `[t_min, t_max)`.
+    fn sample(&self, ray: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> VolumeSample;
+
+    /// Fraction of light transmitted along `ray` over `[t_min, t_max)`, estimated with `sampler`
+    /// when the volume isn't homogeneous.
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Color;
+
+    /// Weight of `ray` passing through `[t_min, t_max)` without a collision drawn by `sample`,
+    /// for when another medium scatters the ray first.
+    fn pass_weight(&self, ray: Ray, t_min: f64, t_max: f64) -> Color;
+
+    /// Phase function at scattering points.
+    fn phase(&self) -> HenyeyGreenstein;
+
+    /// Describe what makes the volume degenerate, if anything.
+    fn validate(&self) -> Result<(), String> {
+        Ok(())
+    }
+}
+
+
+/// ConstantMedium fills the inside of a closed `boundary` object with a homogeneous medium.
+pub struct ConstantMedium {
+    boundary: Rc<dyn Object>,
+    medium: Medium
+}
+
+impl ConstantMedium {
+    pub fn new(boundary: Rc<dyn Object>, medium: Medium) -> Self {
+        Self { boundary, medium }
+    }
+}
+
+impl Volume for ConstantMedium {
+    fn sample(&self, ray: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> VolumeSample {
+        let intervals = inside_intervals(&self.boundary, ray, t_min, t_max);
+        let length = intervals.iter().map(|(start, end)| end - start).sum();
+
+        match self.medium.sample(length, sampler.next_1d()) {
+            VolumeSample::Scatter { mut distance, weight } => {
+                // Map the distance traveled inside the medium back onto the ray.
+                let last_end = intervals.last().map_or(t_min, |(_, end)| *end);
+                for (start, end) in intervals {
+                    if distance < end - start {
+                        return VolumeSample::Scatter { distance: start + distance, weight };
+                    }
+                    distance -= end - start;
+                }
+                // Rounding in the sum of `length` may leave the distance just past the last interval.
+                VolumeSample::Scatter { distance: last_end, weight }
+            },
+            pass => pass
+        }
+    }
+
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, _sampler: &mut dyn Sampler) -> Color {
+        let intervals = inside_intervals(&self.boundary, ray, t_min, t_max);
+        self.medium.transmittance(intervals.iter().map(|(start, end)| end - start).sum())
+    }
+
+    fn pass_weight(&self, ray: Ray, t_min: f64, t_max: f64) -> Color {
+        let intervals = inside_intervals(&self.boundary, ray, t_min, t_max);
+        self.medium.pass_weight(intervals.iter().map(|(start, end)| end - start).sum())
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.medium.phase
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.boundary.validate()?;
+        self.medium.validate()
+    }
+}
+
+
+/// GridVolume is a heterogeneous medium whose density is read from a voxel grid.
+/// The grid spans the unit cube of object space, which `transform` places in the scene.
+pub struct GridVolume {
+    grid: Rc<dyn DensityGrid>,
+    transform: Transform,
+    /// Extinction per unit distance at unit density.
+    extinction: f64,
+    /// Fraction of the extinction that scatters instead of being absorbed.
+    albedo: Color,
+    phase: HenyeyGreenstein
+}
+
+impl GridVolume {
+    pub fn new(grid: Rc<dyn DensityGrid>, transform: Transform, extinction: f64, albedo: Color, g: f64) -> Self {
+        Self { grid, transform, extinction, albedo, phase: HenyeyGreenstein::new(g) }
+    }
+
+    /// Return the ray in grid space, the world space interval inside the grid
+    /// and the factor converting world distances into grid distances.
+    fn grid_interval(&self, ray: Ray, t_min: f64, t_max: f64) -> Option<(Ray, (f64, f64), f64)> {
+        let (local_ray, scale) = self.transform.inverse_ray(ray);
+        let ((near, _), (far, _)) = object::slabs(Point::new(0.0, 0.0, 0.0), Point::new(1.0, 1.0, 1.0), &local_ray)?;
+        let start = f64::max(near / scale, t_min);
+        let end = f64::min(far / scale, t_max);
+        if start < end { Some((local_ray, (start, end), scale)) } else { None }
+    }
+
+    /// Extinction per unit world distance at world distance `t` along the grid space `ray`.
+    fn extinction_at(&self, ray: &Ray, t: f64, scale: f64) -> f64 {
+        self.extinction * self.grid.density(ray.origin + ray.direction * (t * scale))
+    }
+}
+
+impl Volume for GridVolume {
+    /// Delta tracking: take exponential steps against the maximum extinction and
+    /// accept a collision as real with the ratio of the local extinction to the maximum.
+    fn sample(&self, ray: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> VolumeSample {
+        let pass = VolumeSample::Pass { weight: Color::new(1.0, 1.0, 1.0) };
+        let majorant = self.extinction * self.grid.max_density();
+        let (local_ray, (mut t, end), scale) = match self.grid_interval(ray, t_min, t_max) {
+            Some(interval) if majorant > 0.0 => interval,
+            _ => return pass
+        };
+
+        loop {
+            let (u1, u2) = sampler.next_2d();
+            t -= (1.0 - u1).ln() / majorant;
+            if t >= end {
+                return pass;
+            }
+            if u2 * majorant < self.extinction_at(&local_ray, t, scale) {
+                return VolumeSample::Scatter { distance: t, weight: self.albedo };
+            }
+        }
+    }
+
+    /// Ratio tracking: take the same steps as delta tracking but weigh
+    /// each of them by the probability of the collision being fictitious.
+    fn transmittance(&self, ray: Ray, t_min: f64, t_max: f64, sampler: &mut dyn Sampler) -> Color {
+        let majorant = self.extinction * self.grid.max_density();
+        let (local_ray, (mut t, end), scale) = match self.grid_interval(ray, t_min, t_max) {
+            Some(interval) if majorant > 0.0 => interval,
+            _ => return Color::new(1.0, 1.0, 1.0)
+        };
+
+        let mut transmittance = 1.0;
+        loop {
+            t -= (1.0 - sampler.next_1d()).ln() / majorant;
+            if t >= end {
+                return Color::new(transmittance, transmittance, transmittance);
+            }
+            transmittance *= 1.0 - self.extinction_at(&local_ray, t, scale) / majorant;
+        }
+    }
+
+    /// Delta tracking passes with the probability of the transmittance, so its weight is one.
+    fn pass_weight(&self, _ray: Ray, _t_min: f64, _t_max: f64) -> Color {
+        Color::new(1.0, 1.0, 1.0)
+    }
+
+    fn phase(&self) -> HenyeyGreenstein {
+        self.phase
+    }
+
+    fn validate(&self) -> Result<(), String> {
+        self.transform.validate()?;
+        let a = self.albedo;
+        if self.extinction < 0.0 || [a.0, a.1, a.2].iter().any(|value| !(0.0..=1.0).contains(value)) {
+            return Err(String::from("grid volume extinction must not be negative and albedo must lie in [0, 1]"));
+        }
+        Ok(())
+    }
+}
+
+
+/// Return the intervals of `ray` inside `boundary`, clipped to `[t_min, t_max)`.
+pub(crate) fn inside_intervals(boundary: &Rc<dyn Object>, ray: Ray, t_min: f64, t_max: f64) -> Vec<(f64, f64)> {
+    let crossings = boundary.hit_all(ray, t_min, utils::INFINITY, &HitRecord::new(Rc::clone(boundary)));
+
+    // The ray starts inside if its first crossing leaves the boundary.
+    let mut start = match crossings.first() {
+        Some(crossing) if !crossing.is_out => Some(t_min),
+        _ => None
+    };
+
+    let mut intervals = Vec::new();
+    for crossing in crossings.iter() {
+        if crossing.distance >= t_max {
+            break;
+        }
+        if crossing.is_out {
+            start = Some(crossing.distance);
+        } else if let Some(start) = start.take() {
+            intervals.push((start, crossing.distance));
+        }
+    }
+    if let Some(start) = start {
+        intervals.push((start, t_max));
+    }
+    intervals
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Dielectric, object::Sphere, sampler::RandomSampler, voxel::DenseGrid};
+
+    #[test]
+    fn constant_medium_converges_to_analytic_transmittance() {
+        let boundary: Rc<dyn Object> = Rc::new(Sphere::new(Point::new(0.0, 0.0, 0.0), 1.0, Rc::new(Dielectric::default())));
+        let medium = Medium::new(Color::new(0.5, 0.25, 0.1), Color::new(0.25, 0.25, 0.25), 0.0);
+        let volume = ConstantMedium::new(boundary, medium);
+        let mut sampler = RandomSampler::new(3);
+
+        // The ray crosses the unit sphere from 1 to 3.
+        let ray = Ray::new(Point::new(-2.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let expected = [(-0.75f64 * 2.0).exp(), (-0.5f64 * 2.0).exp(), (-0.35f64 * 2.0).exp()];
+        let exact = volume.transmittance(ray, 0.0, 10.0, &mut sampler);
+        for (value, expected) in [exact.0, exact.1, exact.2].iter().zip(expected) {
+            assert!((value - expected).abs() < 1E-9);
+        }
+
+        // Passing weights average to the transmittance, and scattering stays inside the sphere.
+        const SAMPLES: usize = 20000;
+        let mut estimate = Color::new(0.0, 0.0, 0.0);
+        for _ in 0..SAMPLES {
+            match volume.sample(ray, 0.0, 10.0, &mut sampler) {
+                VolumeSample::Pass { weight } => estimate += weight / SAMPLES as f64,
+                VolumeSample::Scatter { distance, .. } => assert!((1.0..=3.0).contains(&distance))
+            }
+        }
+        for (value, expected) in [estimate.0, estimate.1, estimate.2].iter().zip(expected) {
+            assert!((value - expected).abs() < 0.02, "{} != {}", value, expected);
+        }
+    }
+
+    /// Volume of `density` everywhere in the cube from -1 to 1, of extinction 0.5 at unit density.
+    fn uniform_grid(density: f64) -> GridVolume {
+        let grid = Rc::new(DenseGrid::new([2, 2, 2], vec![density; 8]));
+        let transform = Transform::scale(Vector::new(2.0, 2.0, 2.0)).then(&Transform::translate(Vector::new(-1.0, -1.0, -1.0)));
+        GridVolume::new(grid, transform, 0.5, Color::new(1.0, 1.0, 1.0), 0.0)
+    }
+
+    #[test]
+    fn grid_volume_tracking_converges_to_analytic_transmittance() {
+        let volume = uniform_grid(1.0);
+        let mut sampler = RandomSampler::new(5);
+
+        // The ray crosses the cube from 2 to 4.
+        let ray = Ray::new(Point::new(-3.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        let expected = (-0.5f64 * 2.0).exp();
+
+        const SAMPLES: usize = 20000;
+        let (mut ratio, mut passes) = (0.0, 0);
+        for _ in 0..SAMPLES {
+            ratio += volume.transmittance(ray, 0.0, 10.0, &mut sampler).0 / SAMPLES as f64;
+            match volume.sample(ray, 0.0, 10.0, &mut sampler) {
+                VolumeSample::Pass { .. } => passes += 1,
+                VolumeSample::Scatter { distance, .. } => assert!((2.0..=4.0).contains(&distance))
+            }
+        }
+        assert!((ratio - expected).abs() < 0.02, "ratio tracking {} != {}", ratio, expected);
+        let delta = passes as f64 / SAMPLES as f64;
+        assert!((delta - expected).abs() < 0.02, "delta tracking {} != {}", delta, expected);
+    }
+
+    #[test]
+    fn empty_grid_volume_is_transparent() {
+        let volume = uniform_grid(0.0);
+        let mut sampler = RandomSampler::new(5);
+        let ray = Ray::new(Point::new(-3.0, 0.0, 0.0), Vector::new(1.0, 0.0, 0.0));
+        for _ in 0..100 {
+            let transmittance = volume.transmittance(ray, 0.0, 10.0, &mut sampler);
+            assert_eq!((transmittance.0, transmittance.1, transmittance.2), (1.0, 1.0, 1.0));
+            assert!(matches!(volume.sample(ray, 0.0, 10.0, &mut sampler), VolumeSample::Pass { .. }));
+        }
+    }
+}
@@ -0,0 +1,343 @@
+use std::collections::HashMap;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read};
+use std::path::Path;
+
+use crate::vector::Vector;
+
+
+/// Scalar density field sampled on a voxel grid.
+pub trait DensityGrid {
+    /// Interpolated density at `position`, with the grid spanning `[0, 1]` on each axis.
+    fn density(&self, position: Vector) -> f64;
+
+    /// Upper bound of the density anywhere in the grid.
+    fn max_density(&self) -> f64;
+}
+
+
+/// Grid storing every voxel, with `x` varying fastest.
+pub struct DenseGrid {
+    size: [usize; 3],
+    data: Vec<f64>,
+    max_density: f64
+}
+
+impl DenseGrid {
+    /// Panic if `data` doesn't have exactly one value per voxel, or has a negative or NaN density.
+    pub fn new(size: [usize; 3], data: Vec<f64>) -> Self {
+        assert_eq!(Some(data.len()), voxel_count(size), "voxel count doesn't match grid size");
+        assert!(data.iter().all(|density| *density >= 0.0), "densities must not be negative");
+        let max_density = data.iter().cloned().fold(0.0, f64::max);
+        Self { size, data, max_density }
+    }
+
+    /// Load a grid from `path`, in the raw format if its extension is `.raw` and as ASCII otherwise.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        let file = BufReader::new(File::open(&path)?);
+        match path.as_ref().extension() {
+            Some(extension) if extension == "raw" => Self::from_raw(file),
+            _ => Self::from_ascii(file)
+        }
+    }
+
+    /// Read the ASCII format: the grid size `nx ny nz` followed by `nx * ny * nz` densities,
+    /// separated by whitespace. Lines starting with `#` are comments.
+    pub fn from_ascii<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut size = Vec::with_capacity(3);
+        let mut data = Vec::new();
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim_start().starts_with('#') {
+                continue;
+            }
+            for token in line.split_whitespace() {
+                if size.len() < 3 {
+                    size.push(token.parse::<usize>().map_err(invalid_data)?);
+                } else {
+                    data.push(parse_density(token)?);
+                }
+            }
+        }
+
+        if size.len() < 3 {
+            return Err(invalid_data("missing grid size"));
+        }
+        let size = [size[0], size[1], size[2]];
+        if Some(data.len()) != voxel_count(size) {
+            return Err(invalid_data("voxel count doesn't match grid size"));
+        }
+        Ok(Self::new(size, data))
+    }
+
+    /// Read the raw format: the grid size as three little-endian `u32`
+    /// followed by the densities as little-endian `f32`.
+    pub fn from_raw<R: Read>(mut reader: R) -> io::Result<Self> {
+        let mut header = [0u8; 12];
+        reader.read_exact(&mut header)?;
+        let mut size = [0usize; 3];
+        for (i, bytes) in header.chunks_exact(4).enumerate() {
+            size[i] = u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        }
+
+        let mut bytes = Vec::new();
+        reader.read_to_end(&mut bytes)?;
+        if Some(bytes.len()) != voxel_count(size).and_then(|count| count.checked_mul(4)) {
+            return Err(invalid_data("voxel count doesn't match grid size"));
+        }
+        let data: Vec<f64> = bytes.chunks_exact(4)
+            .map(|b| f32::from_le_bytes([b[0], b[1], b[2], b[3]]) as f64)
+            .collect();
+        if !data.iter().all(|density| *density >= 0.0) {
+            return Err(invalid_data("densities must not be negative"));
+        }
+        Ok(Self::new(size, data))
+    }
+
+    pub fn size(&self) -> [usize; 3] {
+        self.size
+    }
+
+    pub fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        self.data[(z * self.size[1] + y) * self.size[0] + x]
+    }
+}
+
+impl DensityGrid for DenseGrid {
+    fn density(&self, position: Vector) -> f64 {
+        trilinear(self.size, position, |x, y, z| self.voxel(x, y, z))
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_density
+    }
+}
+
+
+/// Edge length of the cubic bricks of a `SparseGrid`.
+const BRICK: usize = 8;
+
+/// Grid storing only the bricks of `BRICK`^3 voxels that have some density, like OpenVDB leaf nodes.
+pub struct SparseGrid {
+    size: [usize; 3],
+    bricks: HashMap<[usize; 3], Vec<f64>>,
+    max_density: f64
+}
+
+impl SparseGrid {
+    pub fn new(size: [usize; 3]) -> Self {
+        Self { size, bricks: HashMap::new(), max_density: 0.0 }
+    }
+
+    /// Keep only the non-empty bricks of `grid`.
+    pub fn from_dense(grid: &DenseGrid) -> Self {
+        let size = grid.size();
+        let mut sparse = Self::new(size);
+        for z in 0..size[2] {
+            for y in 0..size[1] {
+                for x in 0..size[0] {
+                    sparse.set_voxel(x, y, z, grid.voxel(x, y, z));
+                }
+            }
+        }
+        sparse
+    }
+
+    /// Read the sparse ASCII format: the grid size `nx ny nz` followed by one `x y z density`
+    /// line per non-empty voxel. Lines starting with `#` are comments.
+    pub fn from_ascii<R: BufRead>(reader: R) -> io::Result<Self> {
+        let mut sparse: Option<Self> = None;
+        for line in reader.lines() {
+            let line = line?;
+            if line.trim_start().starts_with('#') || line.trim().is_empty() {
+                continue;
+            }
+            let tokens: Vec<&str> = line.split_whitespace().collect();
+
+            match (&mut sparse, tokens.len()) {
+                (None, 3) => {
+                    let mut size = [0usize; 3];
+                    for (i, token) in tokens.iter().enumerate() {
+                        size[i] = token.parse().map_err(invalid_data)?;
+                    }
+                    sparse = Some(Self::new(size));
+                },
+                (Some(grid), 4) => {
+                    let mut index = [0usize; 3];
+                    for i in 0..3 {
+                        index[i] = tokens[i].parse().map_err(invalid_data)?;
+                        if index[i] >= grid.size[i] {
+                            return Err(invalid_data("voxel outside of grid"));
+                        }
+                    }
+                    let density = parse_density(tokens[3])?;
+                    grid.set_voxel(index[0], index[1], index[2], density);
+                },
+                _ => return Err(invalid_data(format!("unexpected line: {}", line)))
+            }
+        }
+        sparse.ok_or_else(|| invalid_data("missing grid size"))
+    }
+
+    /// Load a grid in the sparse ASCII format from `path`.
+    pub fn load<P: AsRef<Path>>(path: P) -> io::Result<Self> {
+        Self::from_ascii(BufReader::new(File::open(path)?))
+    }
+
+    /// Panic if `density` is negative or NaN.
+    pub fn set_voxel(&mut self, x: usize, y: usize, z: usize, density: f64) {
+        assert!(density >= 0.0, "densities must not be negative");
+        let key = [x / BRICK, y / BRICK, z / BRICK];
+        let index = ((z % BRICK) * BRICK + y % BRICK) * BRICK + x % BRICK;
+        if density == 0.0 {
+            if let Some(brick) = self.bricks.get_mut(&key) {
+                brick[index] = 0.0;
+            }
+            return;
+        }
+        self.bricks.entry(key).or_insert_with(|| vec![0.0; BRICK * BRICK * BRICK])[index] = density;
+        self.max_density = self.max_density.max(density);
+    }
+
+    pub fn voxel(&self, x: usize, y: usize, z: usize) -> f64 {
+        match self.bricks.get(&[x / BRICK, y / BRICK, z / BRICK]) {
+            Some(brick) => brick[((z % BRICK) * BRICK + y % BRICK) * BRICK + x % BRICK],
+            None => 0.0
+        }
+    }
+
+    /// Number of bricks holding density.
+    pub fn brick_count(&self) -> usize {
+        self.bricks.len()
+    }
+}
+
+impl DensityGrid for SparseGrid {
+    fn density(&self, position: Vector) -> f64 {
+        trilinear(self.size, position, |x, y, z| self.voxel(x, y, z))
+    }
+
+    fn max_density(&self) -> f64 {
+        self.max_density
+    }
+}
+
+
+/// Trilinearly interpolate voxel values around `position` in `[0, 1]^3`,
+/// with voxel centers at half-integer grid coordinates.
+fn trilinear<F: Fn(usize, usize, usize) -> f64>(size: [usize; 3], position: Vector, voxel: F) -> f64 {
+    let mut lower = [0usize; 3];
+    let mut upper = [0usize; 3];
+    let mut fraction = [0.0; 3];
+    for axis in 0..3 {
+        if size[axis] == 0 || !(0.0..=1.0).contains(&position[axis]) {
+            return 0.0;
+        }
+        let p = (position[axis] * size[axis] as f64 - 0.5).max(0.0);
+        let index = (p as usize).min(size[axis] - 1);
+        lower[axis] = index;
+        upper[axis] = (index + 1).min(size[axis] - 1);
+        fraction[axis] = (p - index as f64).min(1.0);
+    }
+
+    let mut density = 0.0;
+    for corner in 0..8 {
+        let mut weight = 1.0;
+        let mut index = [0usize; 3];
+        for axis in 0..3 {
+            if corner >> axis & 1 == 1 {
+                index[axis] = upper[axis];
+                weight *= fraction[axis];
+            } else {
+                index[axis] = lower[axis];
+                weight *= 1.0 - fraction[axis];
+            }
+        }
+        if weight > 0.0 {
+            density += weight * voxel(index[0], index[1], index[2]);
+        }
+    }
+    density
+}
+
+/// Number of voxels of a grid of `size`, if it fits in a `usize`.
+fn voxel_count(size: [usize; 3]) -> Option<usize> {
+    size[0].checked_mul(size[1])?.checked_mul(size[2])
+}
+
+/// Parse a density, which must not be negative or NaN.
+fn parse_density(token: &str) -> io::Result<f64> {
+    let density: f64 = token.parse().map_err(invalid_data)?;
+    if density >= 0.0 {
+        Ok(density)
+    } else {
+        Err(invalid_data(format!("invalid density: {}", token)))
+    }
+}
+
+fn invalid_data<E: Into<Box<dyn std::error::Error + Send + Sync>>>(error: E) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, error)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn raw(size: [u32; 3], densities: &[f32]) -> Vec<u8> {
+        let mut bytes = Vec::new();
+        for value in size {
+            bytes.extend_from_slice(&value.to_le_bytes());
+        }
+        for density in densities {
+            bytes.extend_from_slice(&density.to_le_bytes());
+        }
+        bytes
+    }
+
+    #[test]
+    fn dense_ascii_reads_voxels_x_first() {
+        let grid = DenseGrid::from_ascii("# comment\n2 1 2\n0 1\n2 3.5\n".as_bytes()).unwrap();
+        assert_eq!(grid.size(), [2, 1, 2]);
+        assert_eq!((grid.voxel(1, 0, 0), grid.voxel(0, 0, 1), grid.voxel(1, 0, 1)), (1.0, 2.0, 3.5));
+        assert_eq!(grid.max_density(), 3.5);
+    }
+
+    #[test]
+    fn dense_ascii_rejects_bad_sizes_and_densities() {
+        for text in ["2 1", "-1 1 1\n0", "1.5 1 1\n0", "NaN 1 1\n0", "2 1 1\n0", "1 1 1\n-0.5", "1 1 1\nNaN",
+                     "4294967296 4294967296 4294967296\n0"] {
+            assert!(DenseGrid::from_ascii(text.as_bytes()).is_err(), "{:?} was accepted", text);
+        }
+    }
+
+    #[test]
+    fn dense_raw_reads_little_endian_voxels() {
+        let grid = DenseGrid::from_raw(&raw([1, 2, 1], &[0.25, 4.0])[..]).unwrap();
+        assert_eq!(grid.size(), [1, 2, 1]);
+        assert_eq!((grid.voxel(0, 0, 0), grid.voxel(0, 1, 0)), (0.25, 4.0));
+    }
+
+    #[test]
+    fn dense_raw_rejects_bad_sizes_and_densities() {
+        assert!(DenseGrid::from_raw(&raw([2, 1, 1], &[1.0])[..]).is_err());
+        assert!(DenseGrid::from_raw(&raw([1, 1, 1], &[-1.0])[..]).is_err());
+        assert!(DenseGrid::from_raw(&raw([u32::MAX, u32::MAX, u32::MAX], &[1.0])[..]).is_err());
+        assert!(DenseGrid::from_raw(&[0u8; 8][..]).is_err());
+    }
+
+    #[test]
+    fn sparse_ascii_reads_non_empty_voxels() {
+        let grid = SparseGrid::from_ascii("# comment\n16 16 16\n\n1 2 3 0.5\n15 15 15 2\n".as_bytes()).unwrap();
+        assert_eq!((grid.voxel(1, 2, 3), grid.voxel(15, 15, 15), grid.voxel(0, 0, 0)), (0.5, 2.0, 0.0));
+        assert_eq!(grid.brick_count(), 2);
+        assert_eq!(grid.max_density(), 2.0);
+    }
+
+    #[test]
+    fn sparse_ascii_rejects_bad_lines() {
+        for text in ["", "-1 2 2", "2.5 2 2", "2 2 2\n2 0 0 1", "2 2 2\n0 0 0 -1", "2 2 2\n0 0 0 NaN", "2 2 2\n0 0 1"] {
+            assert!(SparseGrid::from_ascii(text.as_bytes()).is_err(), "{:?} was accepted", text);
+        }
+    }
+}